@@ -1,6 +1,8 @@
 use clap::{Parser, Subcommand};
 use clap_complete::Shell;
 
+use crate::format::Format;
+
 #[derive(Parser)]
 #[command(name = "ccp")]
 #[command(author, version, about = "Claude Code Profiles - manage your Claude Code settings")]
@@ -17,7 +19,19 @@ pub enum Commands {
     Interactive,
     
     /// List all available profiles
-    List,
+    List {
+        /// Only show profiles whose name starts with this (e.g. "work/")
+        prefix: Option<String>,
+        /// Show description, tags and last use
+        #[arg(short, long)]
+        long: bool,
+        /// Only list profiles carrying this tag (repeatable: all must match)
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Include archived profiles
+        #[arg(short, long)]
+        all: bool,
+    },
     
     /// Show current active profile
     Current,
@@ -26,26 +40,50 @@ pub enum Commands {
     Use {
         /// Profile name to switch to
         name: String,
+        /// Allow switching to an archived profile
+        #[arg(long)]
+        archived: bool,
     },
     
     /// Create a new profile
     Create {
-        /// Name for the new profile
+        /// Name for the new profile (use "group/name" for namespaces)
         name: String,
         /// Copy settings from existing profile
         #[arg(short, long)]
         from: Option<String>,
+        /// Start from a provider template (see 'ccp template list')
+        #[arg(short, long, conflicts_with = "from")]
+        template: Option<String>,
+        /// Template value, by field name or key path (e.g. AWS_REGION=eu-west-1)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var, requires = "template")]
+        vars: Vec<(String, String)>,
     },
     
     /// Delete a profile
     Delete {
         /// Profile name to delete
         name: String,
-        /// Skip confirmation
+        /// Skip confirmation and delete even if locked
         #[arg(short, long)]
         force: bool,
     },
     
+    /// Restore a deleted profile from the trash
+    Undelete {
+        /// Profile name (the most recently deleted one is restored) or trash id
+        name: String,
+        /// Restore under a different name
+        #[arg(long = "as", value_name = "NAME")]
+        as_name: Option<String>,
+    },
+    
+    /// List or empty deleted profiles
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    
     /// Copy a profile
     Copy {
         /// Source profile
@@ -60,6 +98,9 @@ pub enum Commands {
         old: String,
         /// New profile name
         new: String,
+        /// Rename even if the profile is locked
+        #[arg(short, long)]
+        force: bool,
     },
     
     /// Interactive configuration
@@ -70,6 +111,18 @@ pub enum Commands {
         /// Profile name (positional, same as --profile)
         #[arg(value_name = "PROFILE")]
         name: Option<String>,
+        /// Configure even if the profile is locked
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Edit a profile in $VISUAL/$EDITOR
+    Edit {
+        /// Profile to edit (default: current)
+        name: Option<String>,
+        /// Edit even if the profile is locked
+        #[arg(short, long)]
+        force: bool,
     },
     
     /// Set a configuration value
@@ -79,8 +132,17 @@ pub enum Commands {
         /// Value to set
         value: String,
         /// Profile to modify (default: current)
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "tags")]
         profile: Option<String>,
+        /// Modify every profile carrying this tag (repeatable: all must match)
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Don't ask for confirmation when modifying several profiles
+        #[arg(short, long)]
+        yes: bool,
+        /// Modify locked profiles too
+        #[arg(short, long)]
+        force: bool,
     },
     
     /// Get a configuration value
@@ -97,20 +159,158 @@ pub enum Commands {
         /// Key path to remove
         key: String,
         /// Profile to modify (default: current)
+        #[arg(short, long, conflicts_with = "tags")]
+        profile: Option<String>,
+        /// Modify every profile carrying this tag (repeatable: all must match)
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Don't ask for confirmation when modifying several profiles
         #[arg(short, long)]
+        yes: bool,
+        /// Modify locked profiles too
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Show where each value under a key comes from
+    Explain {
+        /// Key path (e.g., "env" or "env.HTTPS_PROXY")
+        key: String,
+        /// Profile to explain (default: current)
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    
+    /// Show a profile's revision history
+    Log {
+        /// Profile (default: current)
         profile: Option<String>,
     },
     
-    /// Export profile to stdout as JSON
+    /// Print a profile, or an earlier revision with <profile>@<revision>
+    Show {
+        /// Profile name, e.g. "work" or "work@3"
+        target: String,
+    },
+    
+    /// Restore a profile to an earlier revision
+    Revert {
+        /// Profile to revert
+        profile: String,
+        /// Revision number from 'ccp log'
+        revision: u32,
+        /// Revert a locked profile too
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Show or set a profile's description
+    Describe {
+        /// Profile to describe
+        profile: String,
+        /// New description (omit to show all metadata, "" to clear)
+        text: Option<String>,
+        /// Set the profile's owner
+        #[arg(long)]
+        owner: Option<String>,
+        /// Change the description even if the profile is locked
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Protect a profile against accidental changes
+    Lock {
+        /// Profile to lock
+        profile: String,
+    },
+    
+    /// Allow changes to a locked profile again
+    Unlock {
+        /// Profile to unlock
+        profile: String,
+    },
+    
+    /// Hide a profile from list and the selector without deleting it
+    Archive {
+        /// Profile to archive
+        profile: String,
+        /// Archive even if the profile is locked
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Show an archived profile in list and the selector again
+    Unarchive {
+        /// Profile to unarchive
+        profile: String,
+        /// Unarchive even if the profile is locked
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Manage profile tags
+    Tag {
+        #[command(subcommand)]
+        action: TagAction,
+    },
+    
+    /// Show or edit a profile's markdown notes
+    Notes {
+        #[command(subcommand)]
+        action: NotesAction,
+    },
+    
+    /// Create the cross-product of a base profile and lists of values
+    Generate {
+        /// Profile every generated profile starts from
+        #[arg(long)]
+        base: String,
+        /// Key and values: "model=opus,sonnet" or "env.ANTHROPIC_BASE_URL=@urls.txt" (repeatable)
+        #[arg(long = "vary", value_name = "KEY=VALUES", value_parser = parse_var, required = true)]
+        vary: Vec<(String, String)>,
+        /// Name pattern using {base} and the varied keys (default: "{base}-{model}-...")
+        #[arg(long)]
+        name: Option<String>,
+        /// Show what would be created without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Replace profiles that already exist
+        #[arg(long)]
+        overwrite: bool,
+    },
+    
+    /// Manage short aliases for profile names
+    Alias {
+        #[command(subcommand)]
+        action: AliasAction,
+    },
+    
+    /// Export profile to stdout
     Export {
         /// Profile to export (default: current)
+        #[arg(conflicts_with = "tags")]
         name: Option<String>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "json")]
+        format: Format,
+        /// Export a bundle of every profile carrying this tag (repeatable)
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Expand ${VAR} placeholders as they would be applied
+        #[arg(long)]
+        resolved: bool,
     },
     
     /// Import profile from stdin
     Import {
-        /// Name for the imported profile
-        name: String,
+        /// Name for the imported profile (omit to import a bundle from `export --tag`)
+        name: Option<String>,
+        /// Input format, also used for the saved profile file
+        #[arg(short, long, value_enum, default_value = "json")]
+        format: Format,
+        /// Overwrite existing profiles, even locked ones, and accept generators without asking
+        #[arg(long)]
+        force: bool,
     },
     
     /// Compare two profiles
@@ -121,20 +321,85 @@ pub enum Commands {
         profile2: String,
     },
     
-    /// Create a backup of current settings
+    /// Create a backup of current settings, or manage backups
+    #[command(args_conflicts_with_subcommands = true)]
     Backup {
+        #[command(subcommand)]
+        action: Option<BackupAction>,
         /// Custom backup name
         name: Option<String>,
     },
     
-    /// Restore from a backup
+    /// Restore settings from a backup (or a profile), or save a backup as a profile
     Restore {
-        /// Backup name to restore
-        backup: String,
+        /// Backup to restore, or a profile with --profile (omit to pick a backup)
+        name: Option<String>,
+        /// Restore from a backup (the default), never pointing at a profile of
+        /// the same name
+        #[arg(long, conflicts_with = "profile")]
+        backup: bool,
+        /// Restore from a profile instead of a backup
+        #[arg(long, conflicts_with = "to_profile")]
+        profile: bool,
+        /// Write the backup into this profile instead of settings.json
+        #[arg(long, value_name = "PROFILE")]
+        to_profile: Option<String>,
+        /// With --to-profile, also switch to that profile
+        #[arg(long, requires = "to_profile")]
+        switch: bool,
+        /// With --to-profile, overwrite an existing (even locked) profile
+        #[arg(short, long, requires = "to_profile")]
+        force: bool,
+    },
+    
+    /// Save or restore the whole ccp state: profiles, metadata and settings
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
     },
     
+    /// Undo the last change made by ccp
+    Undo {
+        /// Number of changes to undo
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+        /// Overwrite files that changed since, e.g. settings.json edited by Claude Code
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Redo changes reverted by undo
+    Redo {
+        /// Number of changes to redo
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+        /// Overwrite files that changed since the undo
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Show the changes that can be undone and redone
+    Journal,
+    
     /// Initialize profiles directory structure
-    Init,
+    Init {
+        /// Also make the profiles directory a git repository that ccp commits to
+        #[arg(long)]
+        git: bool,
+    },
+    
+    /// Run git in the profiles directory
+    Git {
+        /// Arguments passed to git
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    
+    /// List and inspect profile templates
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
     
     /// Generate shell completions
     Completions {
@@ -143,3 +408,260 @@ pub enum Commands {
         shell: Shell,
     },
 }
+
+impl Commands {
+    /// Commit message for a command that may change the profiles directory,
+    /// used when it is a git repository. Read-only commands return `None`.
+    /// Values are left out, since they may be secrets.
+    pub fn mutation_summary(&self) -> Option<String> {
+        let target = |profile: &Option<String>| {
+            profile.clone().unwrap_or_else(|| "the current profile".to_string())
+        };
+        let scope = |profile: &Option<String>, tags: &[String]| {
+            if tags.is_empty() {
+                target(profile)
+            } else {
+                format!("profiles tagged {}", tags.join(", "))
+            }
+        };
+        Some(match self {
+            Commands::Create { name, .. } => format!("Create profile {}", name),
+            Commands::Delete { name, .. } => format!("Delete profile {}", name),
+            Commands::Undelete { name, as_name } => match as_name {
+                Some(as_name) => format!("Undelete profile {} as {}", name, as_name),
+                None => format!("Undelete profile {}", name),
+            },
+            Commands::Copy { src, dst } => format!("Copy profile {} to {}", src, dst),
+            Commands::Rename { old, new, .. } => format!("Rename profile {} to {}", old, new),
+            Commands::Configure { profile, name, .. } => {
+                format!("Configure {}", target(&profile.clone().or(name.clone())))
+            }
+            Commands::Edit { name, .. } => format!("Edit {}", target(name)),
+            Commands::Set { key, profile, tags, .. } => format!("Set {} in {}", key, scope(profile, tags)),
+            Commands::Unset { key, profile, tags, .. } => format!("Unset {} in {}", key, scope(profile, tags)),
+            Commands::Revert { profile, revision, .. } => format!("Revert {} to revision {}", profile, revision),
+            Commands::Describe { profile, text: Some(_), .. } | Commands::Describe { profile, owner: Some(_), .. } => {
+                format!("Describe {}", profile)
+            }
+            Commands::Lock { profile } => format!("Lock {}", profile),
+            Commands::Unlock { profile } => format!("Unlock {}", profile),
+            Commands::Archive { profile, .. } => format!("Archive {}", profile),
+            Commands::Unarchive { profile, .. } => format!("Unarchive {}", profile),
+            Commands::Tag { action: TagAction::Add { profile, tags, .. } } => format!("Tag {} with {}", profile, tags.join(", ")),
+            Commands::Tag { action: TagAction::Remove { profile, tags, .. } } => format!("Untag {} from {}", tags.join(", "), profile),
+            Commands::Notes { action: NotesAction::Edit { profile, .. } } => format!("Edit notes of {}", target(profile)),
+            Commands::Generate { base, dry_run: false, .. } => format!("Generate profiles from {}", base),
+            Commands::Alias { action: AliasAction::Set { alias, profile } } => format!("Alias {} to {}", alias, profile),
+            Commands::Alias { action: AliasAction::Remove { alias } } => format!("Remove alias {}", alias),
+            Commands::Import { name: Some(name), .. } => format!("Import profile {}", name),
+            Commands::Import { name: None, .. } => "Import profile bundle".to_string(),
+            Commands::Restore { name, to_profile: Some(profile), .. } => {
+                format!("Save backup {} as profile {}", name.as_deref().unwrap_or("(picked)"), profile)
+            }
+            Commands::Snapshot { action: SnapshotAction::Restore { name, .. } } => format!("Restore snapshot {}", name),
+            Commands::Undo { steps, .. } => format!("Undo {} change(s)", steps),
+            Commands::Redo { steps, .. } => format!("Redo {} change(s)", steps),
+            _ => return None,
+        })
+    }
+    
+    /// Label for the undo journal. Covers everything that changes profiles,
+    /// plus switching profiles and restoring settings, which change only
+    /// settings.json and `.current`. Undo and redo are not journaled.
+    pub fn journal_label(&self) -> Option<String> {
+        match self {
+            Commands::Undo { .. } | Commands::Redo { .. } => None,
+            Commands::Use { name, .. } => Some(format!("Switch to {}", name)),
+            Commands::Interactive => Some("Switch profile".to_string()),
+            Commands::Restore { name, .. } => {
+                Some(format!("Restore {}", name.as_deref().unwrap_or("a backup")))
+            }
+            Commands::Init { .. } => Some("Initialize profiles".to_string()),
+            Commands::Trash { action: TrashAction::Empty { .. } } => Some("Empty trash".to_string()),
+            _ => self.mutation_summary(),
+        }
+    }
+}
+
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", s)),
+    }
+}
+
+#[derive(Subcommand)]
+pub enum AliasAction {
+    /// Create or update an alias
+    Set {
+        /// Alias name
+        alias: String,
+        /// Profile the alias points to
+        profile: String,
+    },
+    
+    /// Remove an alias
+    Remove {
+        /// Alias name
+        alias: String,
+    },
+    
+    /// List all aliases
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum TagAction {
+    /// Add tags to a profile
+    Add {
+        /// Profile to tag
+        profile: String,
+        /// Tags to add
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Tag the profile even if it is locked
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Remove tags from a profile
+    Remove {
+        /// Profile to untag
+        profile: String,
+        /// Tags to remove
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// Untag the profile even if it is locked
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// List tags of a profile, or all tags in use
+    List {
+        /// Profile to show tags for
+        profile: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NotesAction {
+    /// Print a profile's notes
+    Show {
+        /// Profile (default: current)
+        profile: Option<String>,
+    },
+    
+    /// Edit a profile's notes in $VISUAL/$EDITOR
+    Edit {
+        /// Profile (default: current)
+        profile: Option<String>,
+        /// Edit the notes even if the profile is locked
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TemplateAction {
+    /// List built-in and user templates
+    List,
+    
+    /// Show a template's settings and fields
+    Show {
+        /// Template name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BackupAction {
+    /// List backups with time, size, origin and active profile
+    List,
+    
+    /// Show a backup's details and contents, with secrets redacted
+    Show {
+        /// Backup name
+        name: String,
+    },
+    
+    /// Delete a backup
+    Delete {
+        /// Backup name
+        name: String,
+        /// Skip confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Delete old backups
+    Prune {
+        /// Keep this many of the newest backups
+        #[arg(long)]
+        keep: Option<usize>,
+        /// Only delete backups older than this (e.g. 30d, 12h, 2w)
+        #[arg(long, value_name = "AGE")]
+        older_than: Option<String>,
+        /// Only consider backups whose name starts with this
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Also delete backups that were given a name explicitly
+        #[arg(long)]
+        include_named: bool,
+        /// Show what would be deleted without deleting
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Show or set the retention applied after every backup
+    Retention {
+        /// Keep this many of the newest backups
+        #[arg(long, conflicts_with = "off")]
+        keep: Option<usize>,
+        /// Delete backups older than this (e.g. 30d)
+        #[arg(long, value_name = "AGE", conflicts_with = "off")]
+        older_than: Option<String>,
+        /// Stop pruning automatically
+        #[arg(long)]
+        off: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TrashAction {
+    /// List deleted profiles, newest first
+    List,
+    
+    /// Permanently delete profiles in the trash
+    Empty {
+        /// Only those deleted longer ago than this (e.g. 30d, 12h, 2w)
+        #[arg(long, value_name = "AGE")]
+        older_than: Option<String>,
+        /// Skip confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotAction {
+    /// Capture all profiles, metadata and settings into one archive
+    Create {
+        /// Snapshot name (defaults to a timestamp)
+        name: Option<String>,
+    },
+    
+    /// List snapshots, newest first
+    List,
+    
+    /// Replace all profiles and settings with a snapshot's
+    Restore {
+        /// Snapshot name
+        name: String,
+        /// Skip confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Restore even if it changes locked profiles
+        #[arg(short, long)]
+        force: bool,
+    },
+}
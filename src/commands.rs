@@ -1,18 +1,47 @@
 use anyhow::{bail, Context, Result};
-use chrono::Local;
+use chrono::{DateTime, Local};
 use clap::CommandFactory;
-use clap_complete::{generate, Shell};
+use clap_complete::Shell;
 use colored::*;
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Password, Select};
 use similar::{ChangeTag, TextDiff};
-use std::io::{self, Read};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process;
 
 use crate::cli::Cli;
+use crate::conditions::{self, Machine};
+use crate::config::{validate_name, validate_profile_name};
+use crate::format::Format;
+use crate::git;
+use crate::journal::{self, Journal};
+use crate::meta::{self, BackupOrigin, ProfileMeta};
+use crate::prefs::{self, Retention};
 use crate::profile::ProfileManager;
+use crate::snapshot::{self, Snapshot};
+use crate::template;
+
+/// The profile a command acts on: `name` (resolved through aliases and
+/// prefixes) if given, otherwise the current profile (or "default").
+fn target_profile(pm: &ProfileManager, name: Option<&str>) -> Result<String> {
+    match name {
+        Some(p) => pm.resolve_name(p),
+        None => {
+            let profile_name = pm.get_current_profile()?.unwrap_or_else(|| "default".to_string());
+            if !pm.profile_exists(&profile_name) {
+                bail!("Profile '{}' does not exist", profile_name);
+            }
+            Ok(profile_name)
+        }
+    }
+}
 
 pub fn interactive() -> Result<()> {
     let pm = ProfileManager::new()?;
-    let profiles = pm.list_profiles()?;
+    let profiles = pm.list_unarchived_profiles()?;
     
     if profiles.is_empty() {
         println!("{}", "No profiles found. Run 'ccp init' to initialize.".yellow());
@@ -20,22 +49,18 @@ pub fn interactive() -> Result<()> {
     }
     
     let current = pm.get_current_profile()?;
-    let current_idx = current
-        .as_ref()
-        .and_then(|c| profiles.iter().position(|p| p == c))
-        .unwrap_or(0);
-    
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select profile")
-        .items(&profiles)
-        .default(current_idx)
-        .interact_opt()?;
-    
-    match selection {
-        Some(idx) => {
-            let selected = &profiles[idx];
-            if Some(selected) != current.as_ref() {
-                use_profile(selected)?;
+    
+    let mut descriptions = BTreeMap::new();
+    for profile in &profiles {
+        if let Some(description) = pm.load_meta(profile)?.description {
+            descriptions.insert(profile.clone(), description);
+        }
+    }
+    
+    match select_profile(&profiles, &descriptions, current.as_deref())? {
+        Some(selected) => {
+            if Some(&selected) != current.as_ref() {
+                use_profile(&selected, false)?;
             } else {
                 println!("{} Already on '{}'", "·".dimmed(), selected.cyan());
             }
@@ -48,7 +73,88 @@ pub fn interactive() -> Result<()> {
     Ok(())
 }
 
-pub fn init() -> Result<()> {
+/// Pick a profile one namespace level at a time: groups show up as `name/`
+/// entries to drill into, and `..` goes back up a level.
+fn select_profile(
+    profiles: &[String],
+    descriptions: &BTreeMap<String, String>,
+    current: Option<&str>,
+) -> Result<Option<String>> {
+    let mut group = String::new();
+    
+    loop {
+        // (full name, is group) for everything directly inside `group`
+        let mut entries: Vec<(String, bool)> = Vec::new();
+        for profile in profiles.iter().filter(|p| p.starts_with(&group)) {
+            match profile[group.len()..].split_once('/') {
+                Some((sub, _)) => {
+                    let full = format!("{}{}/", group, sub);
+                    if entries.last().map(|(e, _)| e) != Some(&full) {
+                        entries.push((full, true));
+                    }
+                }
+                None => entries.push((profile.clone(), false)),
+            }
+        }
+        
+        let mut items: Vec<String> = entries.iter()
+            .map(|(full, is_group)| {
+                let label = &full[group.len()..];
+                if *is_group {
+                    let count = profiles.iter().filter(|p| p.starts_with(full.as_str())).count();
+                    format!("{} ({})", label, count)
+                } else {
+                    match descriptions.get(full) {
+                        Some(description) => format!("{}  {}", label, description.dimmed()),
+                        None => label.to_string(),
+                    }
+                }
+            })
+            .collect();
+        
+        let offset = if group.is_empty() { 0 } else { 1 };
+        if offset == 1 {
+            items.insert(0, "..".to_string());
+        }
+        
+        let default_idx = current
+            .and_then(|c| entries.iter().position(|(full, is_group)| {
+                if *is_group { c.starts_with(full.as_str()) } else { c == full }
+            }))
+            .map_or(0, |i| i + offset);
+        
+        let prompt = if group.is_empty() {
+            "Select profile".to_string()
+        } else {
+            format!("Select profile in {}", group)
+        };
+        
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(&items)
+            .default(default_idx)
+            .interact_opt()?;
+        
+        match selection {
+            None => return Ok(None),
+            Some(0) if offset == 1 => {
+                // Drop the last "name/" component
+                let trimmed = group.trim_end_matches('/');
+                group = trimmed.rfind('/').map_or(String::new(), |i| group[..=i].to_string());
+            }
+            Some(idx) => {
+                let (full, is_group) = &entries[idx - offset];
+                if *is_group {
+                    group = full.clone();
+                } else {
+                    return Ok(Some(full.clone()));
+                }
+            }
+        }
+    }
+}
+
+pub fn init(with_git: bool) -> Result<()> {
     let pm = ProfileManager::new()?;
     pm.config.ensure_dirs()?;
     
@@ -74,32 +180,137 @@ pub fn init() -> Result<()> {
     println!("  Profiles dir: {}", pm.config.profiles_dir.display());
     println!("  Backups dir: {}", pm.config.backups_dir.display());
     
+    if with_git {
+        let dir = &pm.config.profiles_dir;
+        if git::init(dir)? {
+            println!("{} Initialized a git repository in the profiles directory", "✓".green());
+        } else {
+            println!("{} Profiles directory is already a git repository", "✓".green());
+        }
+        git::commit_all(dir, "Initialize ccp profiles")?;
+        println!("  Changes are committed automatically; use 'ccp git <args>' for anything else.");
+    }
+    
+    Ok(())
+}
+
+/// Run git in the profiles directory, passing its exit code through.
+pub fn git(args: &[String]) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let dir = &pm.config.profiles_dir;
+    if !git::is_repo(dir) {
+        bail!("The profiles directory is not a git repository. Run 'ccp init --git' first.");
+    }
+    let status = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .status()
+        .context("Failed to run git. Is it installed?")?;
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
     Ok(())
 }
 
-pub fn list() -> Result<()> {
+/// Commit whatever a command changed, when the profiles directory is a git
+/// repository. The command itself already succeeded, so failures only warn.
+pub fn git_autocommit(message: &str) {
+    let Ok(pm) = ProfileManager::new() else { return };
+    let dir = &pm.config.profiles_dir;
+    if !git::is_repo(dir) {
+        return;
+    }
+    if let Err(e) = git::commit_all(dir, message) {
+        eprintln!("{} Changes were not committed: {:#}", "!".yellow(), e);
+    }
+}
+
+pub fn list(prefix: Option<&str>, long: bool, tags: &[String], all: bool) -> Result<()> {
     let pm = ProfileManager::new()?;
-    let profiles = pm.list_profiles()?;
+    let mut profiles = pm.list_profiles()?;
     let current = pm.get_current_profile()?;
     
+    if !tags.is_empty() {
+        profiles = pm.profiles_with_tags(tags)?;
+        if profiles.is_empty() {
+            println!("{}", format!("No profiles tagged {}", tags.join(", ")).yellow());
+            return Ok(());
+        }
+    }
+    
+    if let Some(prefix) = prefix {
+        profiles.retain(|p| p.starts_with(prefix));
+        if profiles.is_empty() {
+            println!("{}", format!("No profiles matching '{}'", prefix).yellow());
+            return Ok(());
+        }
+    }
+    
+    let mut hidden = 0;
+    if !all {
+        let before = profiles.len();
+        let unarchived = pm.list_unarchived_profiles()?;
+        profiles.retain(|p| unarchived.contains(p));
+        hidden = before - profiles.len();
+    }
+    
     if profiles.is_empty() {
-        println!("{}", "No profiles found. Run 'ccp init' to initialize.".yellow());
+        if hidden > 0 {
+            println!("{}", format!("All {} matching profile(s) are archived. Use 'ccp list --all' to see them.", hidden).yellow());
+        } else {
+            println!("{}", "No profiles found. Run 'ccp init' to initialize.".yellow());
+        }
         return Ok(());
     }
     
     println!("{}", "Available profiles:".bold());
-    for profile in profiles {
-        let marker = if Some(&profile) == current.as_ref() {
+    
+    // Names are sorted, so each namespace's profiles are contiguous
+    let mut open_groups: Vec<&str> = Vec::new();
+    for profile in &profiles {
+        let segments: Vec<&str> = profile.split('/').collect();
+        let (leaf, groups) = segments.split_last().unwrap_or((&"", &[]));
+        
+        let common = open_groups.iter().zip(groups).take_while(|(a, b)| a == b).count();
+        open_groups.truncate(common);
+        for (depth, group) in groups.iter().enumerate().skip(common) {
+            println!("    {}{}", "  ".repeat(depth), format!("{}/", group).bold());
+            open_groups.push(group);
+        }
+        
+        let indent = "  ".repeat(groups.len());
+        let marker = if Some(profile) == current.as_ref() {
             "→".green()
         } else {
             " ".normal()
         };
-        let name = if Some(&profile) == current.as_ref() {
-            profile.green().bold()
+        let name = if Some(profile) == current.as_ref() {
+            leaf.green().bold()
         } else {
-            profile.normal()
+            leaf.normal()
         };
-        println!("  {} {}", marker, name);
+        let meta = pm.load_meta(profile)?;
+        let mut flags = if meta.locked { " 🔒".to_string() } else { String::new() };
+        if meta.archived {
+            flags.push_str(&" (archived)".dimmed().to_string());
+        }
+        if long {
+            println!("  {} {}{}{}  {}", marker, indent, name, flags, meta_summary(&meta));
+        } else {
+            println!("  {} {}{}{}", marker, indent, name, flags);
+        }
+    }
+    
+    if hidden > 0 {
+        println!("{}", format!("  {} archived profile(s) hidden; 'ccp list --all' shows them", hidden).dimmed());
+    }
+    
+    for (name, paths) in pm.list_conflicts()? {
+        let files: Vec<String> = paths.iter()
+            .map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string())
+            .collect();
+        eprintln!("{} Profile '{}' has conflicting files: {}", "!".yellow(), name, files.join(", "));
     }
     
     Ok(())
@@ -120,38 +331,49 @@ pub fn current() -> Result<()> {
     Ok(())
 }
 
-pub fn use_profile(name: &str) -> Result<()> {
+pub fn use_profile(name: &str, archived: bool) -> Result<()> {
     let pm = ProfileManager::new()?;
+    let name = &if archived { pm.resolve_name_with_archived(name)? } else { pm.resolve_name(name)? };
     
-    if !pm.profile_exists(name) {
-        bail!("Profile '{}' does not exist. Use 'ccp list' to see available profiles.", name);
+    if pm.load_meta(name)?.archived && !archived {
+        bail!("Profile '{}' is archived. Use 'ccp use {} --archived', or 'ccp unarchive {}' first.", name, name, name);
     }
     
     // Load profile and apply to settings.json
     let profile_data = pm.load_profile(name)?;
-    pm.save_settings(&profile_data)?;
+    pm.apply_profile(&profile_data)?;
     pm.set_current_profile(name)?;
     
+    let mut meta = pm.load_meta(name)?;
+    meta.last_used = Some(Local::now());
+    pm.save_meta(name, &meta)?;
+    
     println!("{} Switched to profile '{}'", "✓".green(), name.cyan());
     Ok(())
 }
 
-pub fn create(name: &str, from: Option<&str>) -> Result<()> {
+pub fn create(
+    name: &str,
+    from: Option<&str>,
+    template: Option<&str>,
+    vars: &[(String, String)],
+) -> Result<()> {
     let pm = ProfileManager::new()?;
+    validate_profile_name(name)?;
     pm.config.ensure_dirs()?;
     
     if pm.profile_exists(name) {
         bail!("Profile '{}' already exists", name);
     }
     
-    let data = match from {
-        Some(source) => {
-            if !pm.profile_exists(source) {
-                bail!("Source profile '{}' does not exist", source);
-            }
-            pm.load_profile(source)?
+    let data = match (from, template) {
+        (_, Some(template)) => from_template(&pm, template, vars)?,
+        (Some(source), None) => {
+            let source = pm.resolve_name(source)
+                .context("Source profile not found")?;
+            pm.load_profile(&source)?
         }
-        None => {
+        (None, None) => {
             // Try to copy from current settings or create empty
             if pm.config.settings_file.exists() {
                 pm.load_settings()?
@@ -165,21 +387,89 @@ pub fn create(name: &str, from: Option<&str>) -> Result<()> {
     
     pm.save_profile(name, &data)?;
     
-    let source_msg = from.map_or("current settings".to_string(), |s| format!("'{}'", s));
+    let source_msg = match (from, template) {
+        (Some(source), _) => format!("'{}'", source),
+        (None, Some(template)) => format!("template '{}'", template),
+        (None, None) => "current settings".to_string(),
+    };
     println!("{} Created profile '{}' from {}", "✓".green(), name.cyan(), source_msg);
     Ok(())
 }
 
-pub fn delete(name: &str, force: bool) -> Result<()> {
+/// Build a profile from a template, asking for required values that were
+/// not passed with `--var`.
+fn from_template(pm: &ProfileManager, name: &str, vars: &[(String, String)]) -> Result<serde_json::Value> {
+    let template = template::find(&pm.config.templates_dir, name)?;
+    let vars: BTreeMap<String, String> = vars.iter().cloned().collect();
+    let interactive = io::stdin().is_terminal();
+    
+    let values = template.resolve(&vars, |field| {
+        if !interactive {
+            if let Some(default) = &field.default {
+                return Ok(default.clone());
+            }
+            let var = field.key.rsplit('.').next().unwrap_or(&field.key);
+            bail!("Missing required value '{}' ({}). Pass it with --var {}=...", field.key, field.prompt, var);
+        }
+        if field.secret {
+            return Ok(Password::new().with_prompt(&field.prompt).interact()?);
+        }
+        let mut input = Input::<String>::new().with_prompt(&field.prompt);
+        if let Some(default) = &field.default {
+            input = input.default(default.clone());
+        }
+        Ok(input.interact_text()?)
+    })?;
+    
+    let mut data = template.settings.clone();
+    for (key, value) in values {
+        pm.set_value(&mut data, &key, serde_json::Value::String(value))?;
+    }
+    pm.validate_profile(&data)
+        .with_context(|| format!("Template '{}' produced an invalid profile", name))?;
+    Ok(data)
+}
+
+pub fn template_list() -> Result<()> {
+    let pm = ProfileManager::new()?;
+    
+    println!("{}", "Available templates:".bold());
+    for template in template::all(&pm.config.templates_dir)? {
+        let origin = if template.builtin { "" } else { " (user)" };
+        println!("  {:<12} {}{}", template.name.cyan(), template.description, origin.dimmed());
+    }
+    println!("\nUser templates: {}", pm.config.templates_dir.display().to_string().dimmed());
+    Ok(())
+}
+
+pub fn template_show(name: &str) -> Result<()> {
     let pm = ProfileManager::new()?;
+    let template = template::find(&pm.config.templates_dir, name)?;
     
-    if !pm.profile_exists(name) {
-        bail!("Profile '{}' does not exist", name);
+    println!("{}  {}", template.name.bold(), template.description);
+    println!("\n{}", "Settings:".bold());
+    println!("{}", serde_json::to_string_pretty(&template.settings)?);
+    
+    if !template.fields.is_empty() {
+        println!("\n{}", "Fields:".bold());
+    }
+    for field in &template.fields {
+        let kind = if field.required { "required".yellow() } else { "optional".dimmed() };
+        let default = field.default.as_ref()
+            .map_or(String::new(), |d| format!(" (default: {})", d));
+        println!("  {:<36} {:<8} {}{}", field.key.cyan(), kind, field.prompt, default);
     }
+    Ok(())
+}
+
+pub fn delete(name: &str, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let name = &pm.resolve_exact_name(name)?;
     
     if name == "default" && !force {
         bail!("Cannot delete 'default' profile. Use --force to override.");
     }
+    pm.check_unlocked(name)?;
     
     if !force {
         let confirm = Confirm::new()
@@ -195,73 +485,157 @@ pub fn delete(name: &str, force: bool) -> Result<()> {
     
     // If deleting current profile, switch to default
     if let Some(current) = pm.get_current_profile()? {
-        if current == name {
-            if pm.profile_exists("default") && name != "default" {
-                use_profile("default")?;
-            }
+        if &current == name && pm.profile_exists("default") && name != "default" {
+            use_profile("default", true)?;
         }
     }
     
     pm.delete_profile(name)?;
-    println!("{} Deleted profile '{}'", "✓".green(), name);
+    for alias in pm.retarget_aliases(name, None)? {
+        println!("{} Removed alias '{}'", "·".dimmed(), alias);
+    }
+    println!("{} Deleted profile '{}' (moved to trash; 'ccp undelete {}' brings it back)", "✓".green(), name, name);
     Ok(())
 }
 
-pub fn copy(src: &str, dst: &str) -> Result<()> {
+pub fn undelete(name: &str, as_name: Option<&str>) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let trash = pm.list_trash()?;
+    
+    // A trash id names one entry; a profile name means its latest deletion
+    let Some((id, info)) = trash.iter()
+        .find(|(id, _)| id == name)
+        .or_else(|| trash.iter().find(|(_, info)| info.name == name))
+    else {
+        bail!("No deleted profile '{}' in the trash. See 'ccp trash list'.", name);
+    };
+    
+    let restored = pm.undelete(id, info, as_name)?;
+    println!(
+        "{} Restored profile '{}' {}",
+        "✓".green(),
+        restored.cyan(),
+        format!("(deleted {})", relative_time(info.deleted)).dimmed(),
+    );
+    Ok(())
+}
+
+pub fn trash_list() -> Result<()> {
     let pm = ProfileManager::new()?;
+    let trash = pm.list_trash()?;
     
-    if !pm.profile_exists(src) {
-        bail!("Source profile '{}' does not exist", src);
+    if trash.is_empty() {
+        println!("{}", "The trash is empty.".yellow());
+        return Ok(());
     }
     
-    if pm.profile_exists(dst) {
-        bail!("Destination profile '{}' already exists", dst);
+    let width = trash.iter().map(|(_, info)| info.name.len()).max().unwrap_or(0);
+    println!("{}", "Deleted profiles (newest first):".bold());
+    for (id, info) in &trash {
+        println!(
+            "  {:<width$}  {}  {}",
+            info.name.cyan(),
+            format!("{} ({})", info.deleted.format("%Y-%m-%d %H:%M"), relative_time(info.deleted)).dimmed(),
+            id.dimmed(),
+            width = width,
+        );
     }
+    println!();
+    println!("Restore one with 'ccp undelete <name>' (or its id, for older copies).");
+    Ok(())
+}
+
+pub fn trash_empty(older_than: Option<&str>, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let mut trash = pm.list_trash()?;
     
-    let data = pm.load_profile(src)?;
-    pm.save_profile(dst, &data)?;
+    if let Some(age) = older_than {
+        let cutoff = Local::now()
+            .checked_sub_signed(prefs::parse_age(age)?)
+            .with_context(|| format!("Invalid age '{}': age too large", age))?;
+        trash.retain(|(_, info)| info.deleted < cutoff);
+    }
+    if trash.is_empty() {
+        println!("{} Nothing to remove", "·".dimmed());
+        return Ok(());
+    }
     
-    println!("{} Copied '{}' to '{}'", "✓".green(), src, dst.cyan());
+    if !force {
+        let confirm = Confirm::new()
+            .with_prompt(format!("Permanently delete {} profile(s) from the trash?", trash.len()))
+            .default(false)
+            .interact()?;
+        if !confirm {
+            println!("Cancelled");
+            return Ok(());
+        }
+    }
+    
+    for (id, _) in &trash {
+        pm.purge_trash(id)?;
+    }
+    println!("{} Removed {} profile(s) from the trash", "✓".green(), trash.len());
     Ok(())
 }
 
-pub fn rename(old: &str, new: &str) -> Result<()> {
+pub fn copy(src: &str, dst: &str) -> Result<()> {
     let pm = ProfileManager::new()?;
+    let src = &pm.resolve_name(src).context("Source profile not found")?;
+    validate_profile_name(dst)?;
     
-    if !pm.profile_exists(old) {
-        bail!("Profile '{}' does not exist", old);
+    if pm.profile_exists(dst) {
+        bail!("Destination profile '{}' already exists", dst);
     }
     
+    pm.copy_profile(src, dst)?;
+    
+    println!("{} Copied '{}' to '{}'", "✓".green(), src, dst.cyan());
+    Ok(())
+}
+
+pub fn rename(old: &str, new: &str, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let old = &pm.resolve_exact_name(old)?;
+    validate_profile_name(new)?;
+    pm.check_unlocked(old)?;
+    
     if pm.profile_exists(new) {
         bail!("Profile '{}' already exists", new);
     }
     
-    let data = pm.load_profile(old)?;
-    pm.save_profile(new, &data)?;
-    pm.delete_profile(old)?;
+    pm.rename_profile(old, new)?;
     
     // Update current if renamed
     if let Some(current) = pm.get_current_profile()? {
-        if current == old {
+        if &current == old {
             pm.set_current_profile(new)?;
         }
     }
+    pm.retarget_aliases(old, Some(new))?;
     
     println!("{} Renamed '{}' to '{}'", "✓".green(), old, new.cyan());
     Ok(())
 }
 
-pub fn configure(profile: Option<&str>) -> Result<()> {
-    let pm = ProfileManager::new()?;
-    
-    let profile_name = match profile {
-        Some(p) => p.to_string(),
-        None => pm.get_current_profile()?.unwrap_or_else(|| "default".to_string()),
-    };
-    
-    if !pm.profile_exists(&profile_name) {
-        bail!("Profile '{}' does not exist", profile_name);
+/// Save a profile and re-apply it if it is the current one. Variables are
+/// resolved before anything is written, so a profile that cannot be applied
+/// is not saved either. Returns whether settings.json was updated.
+fn save_and_apply(pm: &ProfileManager, name: &str, data: &serde_json::Value) -> Result<bool> {
+    let is_current = pm.get_current_profile()?.as_deref() == Some(name);
+    let resolved = if is_current { Some(pm.resolve_profile(data)?) } else { None };
+    
+    pm.save_profile(name, data)?;
+    if let Some(resolved) = &resolved {
+        pm.save_settings(resolved)?;
     }
+    Ok(is_current)
+}
+
+pub fn configure(profile: Option<&str>, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    
+    let profile_name = target_profile(&pm, profile)?;
+    pm.check_unlocked(&profile_name)?;
     
     let mut data = pm.load_profile(&profile_name)?;
     
@@ -328,11 +702,7 @@ pub fn configure(profile: Option<&str>) -> Result<()> {
         .interact()?;
     data["alwaysThinkingEnabled"] = serde_json::Value::Bool(thinking);
     
-    pm.save_profile(&profile_name, &data)?;
-    
-    // Apply if current profile
-    if Some(&profile_name) == pm.get_current_profile()?.as_ref() {
-        pm.save_settings(&data)?;
+    if save_and_apply(&pm, &profile_name, &data)? {
         println!("\n{} Configuration saved and applied", "✓".green());
     } else {
         println!("\n{} Configuration saved", "✓".green());
@@ -341,32 +711,133 @@ pub fn configure(profile: Option<&str>) -> Result<()> {
     Ok(())
 }
 
-pub fn set(key: &str, value: &str, profile: Option<&str>) -> Result<()> {
-    let pm = ProfileManager::new()?;
-    
-    let profile_name = match profile {
-        Some(p) => p.to_string(),
-        None => pm.get_current_profile()?.unwrap_or_else(|| "default".to_string()),
-    };
-    
-    if !pm.profile_exists(&profile_name) {
-        bail!("Profile '{}' does not exist", profile_name);
+pub fn edit(profile: Option<&str>, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+
+    let profile_name = target_profile(&pm, profile)?;
+    pm.check_unlocked(&profile_name)?;
+
+    if pm.is_script_profile(&profile_name) {
+        bail!(
+            "Profile '{}' is generated by {}; edit the script instead",
+            profile_name,
+            pm.config.script_path(&profile_name).display()
+        );
     }
+    let (path, format) = pm.profile_file(&profile_name)?
+        .with_context(|| format!("Profile '{}' does not exist", profile_name))?;
+    let original = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    // Edit a temp copy so a half-written or invalid file never replaces the profile
+    let mut temp = tempfile::Builder::new()
+        .prefix("ccp-edit-")
+        .suffix(&format!(".{}", format.extension()))
+        .tempfile()
+        .context("Failed to create temporary file")?;
+    temp.write_all(original.as_bytes())?;
+    temp.flush()?;
+
+    let is_current = pm.get_current_profile()?.as_deref() == Some(profile_name.as_str());
     
-    let mut data = pm.load_profile(&profile_name)?;
+    loop {
+        run_editor(temp.path())?;
+
+        let content = fs::read_to_string(temp.path())
+            .context("Failed to read edited file")?;
+        if content == original {
+            println!("{} No changes to '{}'", "·".dimmed(), profile_name);
+            return Ok(());
+        }
+
+        let parsed = format.parse(&content)
+            .context(format!("Invalid {}", format.extension().to_uppercase()))
+            .and_then(|data| pm.validate_profile(&data).map(|_| data))
+            // The current profile gets applied, so it must resolve too
+            .and_then(|data| if is_current { pm.resolve_profile(&data).map(|_| data) } else { Ok(data) });
+
+        match parsed {
+            Ok(data) => {
+                if save_and_apply(&pm, &profile_name, &data)? {
+                    println!("{} Saved and applied '{}'", "✓".green(), profile_name.cyan());
+                } else {
+                    println!("{} Saved '{}'", "✓".green(), profile_name.cyan());
+                }
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{} {:#}", "✗".red(), e);
+                let retry = Confirm::new()
+                    .with_prompt("Re-open the editor?")
+                    .default(true)
+                    .interact()?;
+
+                if !retry {
+                    println!("Cancelled, '{}' left unchanged", profile_name);
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Run `$VISUAL` or `$EDITOR` (falling back to `vi`) on `path` and wait for it to exit.
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .or_else(|| env::var("EDITOR").ok().filter(|e| !e.trim().is_empty()))
+        .unwrap_or_else(|| "vi".to_string());
+
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("Editor command is empty")?;
+
+    let status = process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to launch editor '{}'", editor))?;
+
+    if !status.success() {
+        bail!("Editor '{}' exited with {}", editor, status);
+    }
+    Ok(())
+}
+
+pub fn set(
+    key: &str,
+    value: &str,
+    profile: Option<&str>,
+    tags: &[String],
+    yes: bool,
+    force: bool,
+) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
     
     // Parse value - try as JSON first, then as string
     let json_value: serde_json::Value = serde_json::from_str(value)
         .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
     
-    pm.set_value(&mut data, key, json_value)?;
-    pm.save_profile(&profile_name, &data)?;
-    
-    // Apply if current profile
-    if Some(&profile_name) == pm.get_current_profile()?.as_ref() {
-        pm.save_settings(&data)?;
+    if !tags.is_empty() {
+        return bulk_update(&pm, tags, yes, force, |pm, data| {
+            let old = pm.get_value(data, key);
+            if old.as_ref() == Some(&json_value) {
+                return Ok(None);
+            }
+            let old = old.map_or_else(|| "(not set)".to_string(), |v| v.to_string());
+            pm.set_value(data, key, json_value.clone())?;
+            Ok(Some(format!("{}: {} → {}", key, old, json_value)))
+        });
     }
     
+    let profile_name = target_profile(&pm, profile)?;
+    pm.check_unlocked(&profile_name)?;
+    
+    let mut data = pm.load_profile(&profile_name)?;
+    
+    pm.set_value(&mut data, key, json_value)?;
+    save_and_apply(&pm, &profile_name, &data)?;
+    
     println!("{} Set {}={} in '{}'", "✓".green(), key.cyan(), value, profile_name);
     Ok(())
 }
@@ -374,14 +845,7 @@ pub fn set(key: &str, value: &str, profile: Option<&str>) -> Result<()> {
 pub fn get(key: &str, profile: Option<&str>) -> Result<()> {
     let pm = ProfileManager::new()?;
     
-    let profile_name = match profile {
-        Some(p) => p.to_string(),
-        None => pm.get_current_profile()?.unwrap_or_else(|| "default".to_string()),
-    };
-    
-    if !pm.profile_exists(&profile_name) {
-        bail!("Profile '{}' does not exist", profile_name);
-    }
+    let profile_name = target_profile(&pm, profile)?;
     
     let data = pm.load_profile(&profile_name)?;
     
@@ -398,27 +862,26 @@ pub fn get(key: &str, profile: Option<&str>) -> Result<()> {
     Ok(())
 }
 
-pub fn unset(key: &str, profile: Option<&str>) -> Result<()> {
-    let pm = ProfileManager::new()?;
-    
-    let profile_name = match profile {
-        Some(p) => p.to_string(),
-        None => pm.get_current_profile()?.unwrap_or_else(|| "default".to_string()),
-    };
+pub fn unset(key: &str, profile: Option<&str>, tags: &[String], yes: bool, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
     
-    if !pm.profile_exists(&profile_name) {
-        bail!("Profile '{}' does not exist", profile_name);
+    if !tags.is_empty() {
+        return bulk_update(&pm, tags, yes, force, |pm, data| {
+            if pm.unset_value(data, key)? {
+                Ok(Some(format!("remove {}", key)))
+            } else {
+                Ok(None)
+            }
+        });
     }
     
+    let profile_name = target_profile(&pm, profile)?;
+    pm.check_unlocked(&profile_name)?;
+    
     let mut data = pm.load_profile(&profile_name)?;
     
     if pm.unset_value(&mut data, key)? {
-        pm.save_profile(&profile_name, &data)?;
-        
-        // Apply if current profile
-        if Some(&profile_name) == pm.get_current_profile()?.as_ref() {
-            pm.save_settings(&data)?;
-        }
+        save_and_apply(&pm, &profile_name, &data)?;
         
         println!("{} Removed '{}' from '{}'", "✓".green(), key.cyan(), profile_name);
     } else {
@@ -428,71 +891,826 @@ pub fn unset(key: &str, profile: Option<&str>) -> Result<()> {
     Ok(())
 }
 
-pub fn export(name: Option<&str>) -> Result<()> {
+pub fn explain(key: &str, profile: Option<&str>) -> Result<()> {
     let pm = ProfileManager::new()?;
+    let profile_name = target_profile(&pm, profile)?;
+    let data = pm.load_profile(&profile_name)?;
+    let machine = Machine::current();
     
-    let profile_name = match name {
-        Some(p) => p.to_string(),
-        None => pm.get_current_profile()?.unwrap_or_else(|| "default".to_string()),
-    };
+    let under_key = |path: &str| path == key || path.starts_with(&format!("{}.", key));
     
-    if !pm.profile_exists(&profile_name) {
-        bail!("Profile '{}' does not exist", profile_name);
+    // Who set each leaf value, in the order the layers are merged
+    let mut base = data.clone();
+    let generated = pm.take_generated(&mut base)?;
+    let mut layers = vec![("profile".to_string(), base.clone())];
+    if let Some(generated) = generated {
+        conditions::deep_merge(&mut base, &generated);
+        layers.push(("generator".to_string(), generated));
+    }
+    // Resolve from here on so the generator doesn't run a second time
+    let merged = base.clone();
+    let blocks = match base.as_object_mut().and_then(|m| m.shift_remove("when")) {
+        Some(when) => conditions::parse_blocks(&when)?,
+        None => Vec::new(),
+    };
+    let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut raw: BTreeMap<String, serde_json::Value> = BTreeMap::new();
+    for (label, layer) in layers {
+        for (path, value) in conditions::leaves(&layer, "").into_iter().filter(|(p, _)| under_key(p)) {
+            sources.entry(path.clone()).or_default().push(label.clone());
+            raw.insert(path, value);
+        }
+    }
+    let mut skipped = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        let label = format!("when[{}] ({})", i, block.describe());
+        let paths: Vec<(String, serde_json::Value)> = conditions::leaves(&block.settings, "")
+            .into_iter()
+            .filter(|(p, _)| under_key(p))
+            .collect();
+        if paths.is_empty() {
+            continue;
+        }
+        if !block.matches(&machine) {
+            skipped.push(label);
+            continue;
+        }
+        for (path, value) in paths {
+            sources.entry(path.clone()).or_default().push(label.clone());
+            raw.insert(path, value);
+        }
     }
     
-    let data = pm.load_profile(&profile_name)?;
-    let output = serde_json::to_string_pretty(&data)?;
-    println!("{}", output);
+    println!(
+        "{}",
+        format!("Profile '{}' on hostname={}, user={}", profile_name, machine.hostname, machine.user).dimmed()
+    );
     
+    let resolved = pm.resolve_profile(&merged)?;
+    match pm.get_value(&resolved, key) {
+        None => println!("{} '{}' is not set", "·".dimmed(), key),
+        Some(value) => {
+            for (path, value) in conditions::leaves(&value, key) {
+                println!("{} = {}", path.cyan(), value);
+                let Some(layers) = sources.get(&path) else { continue };
+                if let Some((last, earlier)) = layers.split_last() {
+                    println!("    from {}", last);
+                    for layer in earlier.iter().rev() {
+                        println!("    {}", format!("overrides {}", layer).dimmed());
+                    }
+                }
+                if raw.get(&path).is_some_and(|r| r != &value) {
+                    println!("    {}", format!("expanded from {}", raw[&path]).dimmed());
+                }
+            }
+        }
+    }
+    
+    for label in skipped {
+        println!("{} {} does not match this machine", "·".dimmed(), label);
+    }
     Ok(())
 }
 
-pub fn import(name: &str) -> Result<()> {
+pub fn log(profile: Option<&str>) -> Result<()> {
     let pm = ProfileManager::new()?;
-    pm.config.ensure_dirs()?;
+    let name = target_profile(&pm, profile)?;
     
-    if pm.profile_exists(name) {
-        bail!("Profile '{}' already exists. Delete it first or use a different name.", name);
+    let numbers = pm.revision_numbers(&name)?;
+    if numbers.is_empty() {
+        println!("{}", format!("No history for '{}' yet. A revision is kept each time it changes.", name).yellow());
+        return Ok(());
     }
     
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)
-        .context("Failed to read from stdin")?;
-    
-    let data: serde_json::Value = serde_json::from_str(&input)
-        .context("Failed to parse JSON from stdin")?;
-    
-    pm.save_profile(name, &data)?;
-    
-    eprintln!("{} Imported profile '{}'", "✓".green(), name.cyan());
+    println!("{}", format!("History of '{}' (newest first):", name).bold());
+    for number in numbers.into_iter().rev() {
+        let revision = pm.load_revision(&name, number)?;
+        println!(
+            "  {:>4}  {:<16}  {}  {}",
+            format!("@{}", number).cyan(),
+            relative_time(revision.time).dimmed(),
+            revision.command,
+            format!("({})", revision.summary).dimmed(),
+        );
+    }
+    println!();
+    println!("Each revision is the content before that command ran.");
+    println!("View one with 'ccp show {}@N', restore it with 'ccp revert {} N'.", name, name);
     Ok(())
 }
 
-pub fn diff(profile1: &str, profile2: &str) -> Result<()> {
+/// Print a profile's file, or with `name@N` one of its earlier revisions.
+pub fn show(target: &str) -> Result<()> {
     let pm = ProfileManager::new()?;
     
-    if !pm.profile_exists(profile1) {
-        bail!("Profile '{}' does not exist", profile1);
+    let Some((name, number)) = target.split_once('@') else {
+        let name = pm.resolve_name(target)?;
+        let path = match pm.profile_file(&name)? {
+            Some((path, _)) => path,
+            None => pm.config.script_path(&name),
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        print!("{}", content);
+        if !content.ends_with('\n') {
+            println!();
+        }
+        return Ok(());
+    };
+    
+    let name = pm.resolve_name(name)?;
+    let number: u32 = number.parse()
+        .with_context(|| format!("Invalid revision '{}': expected a number, e.g. {}@3", number, name))?;
+    let revision = pm.load_revision(&name, number)?;
+    eprintln!(
+        "{} '{}' before: {} ({}, {})",
+        "ℹ".blue(),
+        name,
+        revision.command,
+        revision.time.format("%Y-%m-%d %H:%M"),
+        relative_time(revision.time),
+    );
+    print!("{}", revision.content);
+    if !revision.content.ends_with('\n') {
+        println!();
     }
-    if !pm.profile_exists(profile2) {
-        bail!("Profile '{}' does not exist", profile2);
+    Ok(())
+}
+
+pub fn revert(profile: &str, number: u32, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let name = pm.resolve_exact_name(profile)?;
+    pm.check_unlocked(&name)?;
+    
+    let revision = pm.load_revision(&name, number)?;
+    let data = revision.format.parse(&revision.content)
+        .with_context(|| format!("Revision {} of '{}' cannot be parsed", number, name))?;
+    pm.validate_profile(&data)?;
+    
+    // The revert is itself recorded, so it can be reverted too
+    if save_and_apply(&pm, &name, &data)? {
+        println!("{} Reverted '{}' to revision {} and applied it", "✓".green(), name.cyan(), number);
+    } else {
+        println!("{} Reverted '{}' to revision {}", "✓".green(), name.cyan(), number);
+    }
+    Ok(())
+}
+
+/// Apply `change` to every profile carrying all of `tags`. Each profile's
+/// outcome is reported first, then a single confirmation covers them all.
+/// `change` returns a short description of what it did, or `None` if the
+/// profile was already as requested. Locked profiles are skipped unless
+/// `force` is set.
+fn bulk_update<F>(
+    pm: &ProfileManager,
+    tags: &[String],
+    yes: bool,
+    force: bool,
+    mut change: F,
+) -> Result<()>
+where
+    F: FnMut(&ProfileManager, &mut serde_json::Value) -> Result<Option<String>>,
+{
+    let profiles = pm.profiles_with_tags(tags)?;
+    if profiles.is_empty() {
+        bail!("No profiles tagged {}", tags.join(", "));
     }
     
-    let data1 = pm.load_profile(profile1)?;
-    let data2 = pm.load_profile(profile2)?;
-    
-    let json1 = serde_json::to_string_pretty(&data1)?;
-    let json2 = serde_json::to_string_pretty(&data2)?;
+    let mut updates = Vec::new();
+    for profile in &profiles {
+        if !force && pm.load_meta(profile)?.locked {
+            println!("  🔒 {}  {}", profile, "locked, skipped".dimmed());
+            continue;
+        }
+        let mut data = pm.load_profile(profile)?;
+        match change(pm, &mut data).with_context(|| format!("Profile '{}'", profile))? {
+            Some(summary) => {
+                println!("  {} {}  {}", "~".yellow(), profile.cyan(), summary);
+                updates.push((profile, data));
+            }
+            None => println!("  {} {}  {}", "·".dimmed(), profile, "unchanged".dimmed()),
+        }
+    }
     
-    if json1 == json2 {
-        println!("{} Profiles are identical", "=".green());
+    if updates.is_empty() {
+        println!("{} Nothing to change", "·".dimmed());
         return Ok(());
     }
     
-    println!("{} {} vs {}", "Diff:".bold(), profile1.red(), profile2.green());
-    println!();
+    if !yes {
+        let confirm = Confirm::new()
+            .with_prompt(format!("Apply to {} profile(s)?", updates.len()))
+            .default(false)
+            .interact()?;
+        if !confirm {
+            println!("Cancelled");
+            return Ok(());
+        }
+    }
     
-    let diff = TextDiff::from_lines(&json1, &json2);
+    for (profile, data) in &updates {
+        save_and_apply(pm, profile, data)?;
+    }
+    
+    println!("{} Updated {} profile(s)", "✓".green(), updates.len());
+    Ok(())
+}
+
+pub fn lock(profile: &str) -> Result<()> {
+    set_locked(profile, true)
+}
+
+pub fn unlock(profile: &str) -> Result<()> {
+    set_locked(profile, false)
+}
+
+fn set_locked(profile: &str, locked: bool) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let profile_name = target_profile(&pm, Some(profile))?;
+    let mut meta = pm.load_meta(&profile_name)?;
+    
+    let state = if locked { "locked" } else { "unlocked" };
+    if meta.locked == locked {
+        println!("{} '{}' is already {}", "·".dimmed(), profile_name, state);
+        return Ok(());
+    }
+    
+    meta.locked = locked;
+    pm.save_meta(&profile_name, &meta)?;
+    println!("{} Profile '{}' {}", "✓".green(), profile_name.cyan(), state);
+    Ok(())
+}
+
+pub fn archive(profile: &str, force: bool) -> Result<()> {
+    set_archived(profile, true, force)
+}
+
+pub fn unarchive(profile: &str, force: bool) -> Result<()> {
+    set_archived(profile, false, force)
+}
+
+fn set_archived(profile: &str, archived: bool, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let profile_name = target_profile(&pm, Some(profile))?;
+    let mut meta = pm.load_meta(&profile_name)?;
+    
+    let state = if archived { "archived" } else { "unarchived" };
+    if meta.archived == archived {
+        println!("{} '{}' is already {}", "·".dimmed(), profile_name, state);
+        return Ok(());
+    }
+    
+    meta.archived = archived;
+    pm.save_meta(&profile_name, &meta)?;
+    println!("{} Profile '{}' {}", "✓".green(), profile_name.cyan(), state);
+    if archived && pm.get_current_profile()?.as_deref() == Some(profile_name.as_str()) {
+        println!("  It stays active until you switch to another profile.");
+    }
+    Ok(())
+}
+
+pub fn describe(profile: &str, text: Option<&str>, owner: Option<&str>, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let profile_name = target_profile(&pm, Some(profile))?;
+    let mut meta = pm.load_meta(&profile_name)?;
+    
+    if text.is_none() && owner.is_none() {
+        print_meta(&profile_name, &meta);
+        return Ok(());
+    }
+    
+    if let Some(text) = text {
+        meta.description = Some(text.trim().to_string()).filter(|t| !t.is_empty());
+    }
+    if let Some(owner) = owner {
+        meta.owner = Some(owner.trim().to_string()).filter(|o| !o.is_empty());
+    }
+    pm.save_meta(&profile_name, &meta)?;
+    
+    let what = match (text, owner) {
+        (Some(_), Some(_)) => "description and owner",
+        (Some(_), None) => "description",
+        _ => "owner",
+    };
+    println!("{} Updated {} of '{}'", "✓".green(), what, profile_name.cyan());
+    Ok(())
+}
+
+fn print_meta(name: &str, meta: &ProfileMeta) {
+    let show_time = |time: Option<DateTime<Local>>| match time {
+        Some(t) => format!("{} ({})", t.format("%Y-%m-%d %H:%M"), relative_time(t)),
+        None => "unknown".dimmed().to_string(),
+    };
+    
+    println!("{}", name.bold());
+    println!("  Description: {}", meta.description.as_deref().unwrap_or("-"));
+    println!("  Tags:        {}", if meta.tags.is_empty() { "-".to_string() } else { meta.tags.join(", ") });
+    println!("  Owner:       {}", meta.owner.as_deref().unwrap_or("-"));
+    println!("  Created:     {}", show_time(meta.created));
+    println!("  Updated:     {}", show_time(meta.updated));
+    println!("  Last used:   {}", match meta.last_used {
+        Some(t) => show_time(Some(t)),
+        None => "never".dimmed().to_string(),
+    });
+    if let Some(notes) = &meta.notes {
+        println!("  Notes:");
+        for line in notes.lines() {
+            println!("    {}", line);
+        }
+    }
+}
+
+/// One-line summary of a profile's metadata for `list --long`.
+fn meta_summary(meta: &ProfileMeta) -> String {
+    let mut parts = Vec::new();
+    if let Some(description) = &meta.description {
+        parts.push(description.normal().to_string());
+    }
+    if !meta.tags.is_empty() {
+        parts.push(format!("[{}]", meta.tags.join(", ")).cyan().to_string());
+    }
+    let used = match meta.last_used {
+        Some(t) => format!("used {}", relative_time(t)),
+        None => "never used".to_string(),
+    };
+    parts.push(used.dimmed().to_string());
+    parts.join("  ")
+}
+
+/// "3 hours ago" style rendering of a timestamp.
+fn relative_time(time: DateTime<Local>) -> String {
+    let plural = |n: i64, unit: &str| format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" });
+    match (Local::now() - time).num_seconds() {
+        s if s < 60 => "just now".to_string(),
+        s if s < 3600 => plural(s / 60, "minute"),
+        s if s < 86400 => plural(s / 3600, "hour"),
+        s if s < 86400 * 30 => plural(s / 86400, "day"),
+        s if s < 86400 * 365 => plural(s / (86400 * 30), "month"),
+        s => plural(s / (86400 * 365), "year"),
+    }
+}
+
+fn validate_tags(tags: &[String]) -> Result<()> {
+    for tag in tags {
+        if tag.is_empty() || tag.chars().any(|c| c.is_whitespace() || c == ',') {
+            bail!("Invalid tag '{}': tags cannot be empty or contain whitespace or ','", tag);
+        }
+    }
+    Ok(())
+}
+
+pub fn tag_add(profile: &str, tags: &[String], force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let profile_name = target_profile(&pm, Some(profile))?;
+    validate_tags(tags)?;
+    
+    let mut meta = pm.load_meta(&profile_name)?;
+    let added = meta.add_tags(tags);
+    pm.save_meta(&profile_name, &meta)?;
+    
+    if added.is_empty() {
+        println!("{} '{}' already has those tags", "·".dimmed(), profile_name);
+    } else {
+        println!("{} Tagged '{}' with {}", "✓".green(), profile_name.cyan(), added.join(", "));
+    }
+    Ok(())
+}
+
+pub fn tag_remove(profile: &str, tags: &[String], force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let profile_name = target_profile(&pm, Some(profile))?;
+    
+    let mut meta = pm.load_meta(&profile_name)?;
+    let removed = meta.remove_tags(tags);
+    pm.save_meta(&profile_name, &meta)?;
+    
+    if removed.is_empty() {
+        println!("{} '{}' has none of those tags", "!".yellow(), profile_name);
+    } else {
+        println!("{} Removed {} from '{}'", "✓".green(), removed.join(", "), profile_name.cyan());
+    }
+    Ok(())
+}
+
+pub fn tag_list(profile: Option<&str>) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    
+    if let Some(profile) = profile {
+        let profile_name = target_profile(&pm, Some(profile))?;
+        for tag in pm.load_meta(&profile_name)?.tags {
+            println!("{}", tag);
+        }
+        return Ok(());
+    }
+    
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for profile in pm.list_profiles()? {
+        for tag in pm.load_meta(&profile)?.tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    
+    if counts.is_empty() {
+        println!("{}", "No tags yet. Use 'ccp tag add <profile> <tag>'.".yellow());
+        return Ok(());
+    }
+    for (tag, count) in counts {
+        println!("  {} {}", tag.cyan(), format!("({})", count).dimmed());
+    }
+    Ok(())
+}
+
+pub fn notes_show(profile: Option<&str>) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let profile_name = target_profile(&pm, profile)?;
+    
+    match pm.load_meta(&profile_name)?.notes {
+        Some(notes) => println!("{}", notes.trim_end()),
+        None => println!("{}", "(no notes)".dimmed()),
+    }
+    Ok(())
+}
+
+pub fn notes_edit(profile: Option<&str>, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let profile_name = target_profile(&pm, profile)?;
+    pm.check_unlocked(&profile_name)?;
+    let mut meta = pm.load_meta(&profile_name)?;
+    
+    let mut temp = tempfile::Builder::new()
+        .prefix("ccp-notes-")
+        .suffix(".md")
+        .tempfile()
+        .context("Failed to create temporary file")?;
+    temp.write_all(meta.notes.as_deref().unwrap_or("").as_bytes())?;
+    temp.flush()?;
+    
+    run_editor(temp.path())?;
+    
+    let notes = fs::read_to_string(temp.path()).context("Failed to read edited notes")?;
+    let notes = Some(notes.trim_end().to_string()).filter(|n| !n.trim().is_empty());
+    if notes == meta.notes {
+        println!("{} No changes to notes of '{}'", "·".dimmed(), profile_name);
+        return Ok(());
+    }
+    
+    meta.notes = notes;
+    pm.save_meta(&profile_name, &meta)?;
+    println!("{} Saved notes for '{}'", "✓".green(), profile_name.cyan());
+    Ok(())
+}
+
+pub fn generate(
+    base: &str,
+    vary: &[(String, String)],
+    name_pattern: Option<&str>,
+    dry_run: bool,
+    overwrite: bool,
+) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let base = pm.resolve_name(base)?;
+    let base_data = pm.load_profile(&base)?;
+    
+    let mut axes = Vec::new();
+    for (key, spec) in vary {
+        let values = read_variants(spec).with_context(|| format!("Values for '{}'", key))?;
+        if values.is_empty() {
+            bail!("No values given for '{}'", key);
+        }
+        axes.push((key.as_str(), values));
+    }
+    
+    let default_pattern = std::iter::once("{base}".to_string())
+        .chain(axes.iter().map(|(key, _)| format!("{{{}}}", key.rsplit('.').next().unwrap_or(key))))
+        .collect::<Vec<_>>()
+        .join("-");
+    let pattern = name_pattern.unwrap_or(&default_pattern);
+    
+    // Cross-product of all value lists, as (key, value) assignments
+    let mut combinations: Vec<Vec<(&str, &str)>> = vec![Vec::new()];
+    for (key, values) in &axes {
+        combinations = combinations.into_iter()
+            .flat_map(|combo| values.iter().map(move |value| {
+                let mut combo = combo.clone();
+                combo.push((*key, value.as_str()));
+                combo
+            }))
+            .collect();
+    }
+    
+    let mut planned: Vec<(String, serde_json::Value, String)> = Vec::new();
+    for combo in &combinations {
+        let name = render_name(pattern, &base, combo)?;
+        validate_profile_name(&name)
+            .with_context(|| format!("Pattern '{}' gives an invalid name", pattern))?;
+        if planned.iter().any(|(n, _, _)| n == &name) {
+            bail!("Pattern '{}' gives '{}' more than once; add more placeholders to --name", pattern, name);
+        }
+        
+        let mut data = base_data.clone();
+        for (key, value) in combo {
+            // Environment variables are always strings; elsewhere parse like `set`
+            let value = if key.starts_with("env.") {
+                serde_json::Value::String(value.to_string())
+            } else {
+                serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_string()))
+            };
+            pm.set_value(&mut data, key, value)?;
+        }
+        pm.validate_profile(&data).with_context(|| format!("Profile '{}'", name))?;
+        
+        let summary = combo.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(", ");
+        planned.push((name, data, summary));
+    }
+    
+    let (mut created, mut replaced, mut skipped) = (0, 0, 0);
+    for (name, data, summary) in &planned {
+        let exists = pm.profile_exists(name);
+        let locked = exists && pm.load_meta(name)?.locked;
+        let (marker, status, write) = match (exists, overwrite && !locked) {
+            (false, _) => {
+                created += 1;
+                ("+".green(), "created".green(), true)
+            }
+            (true, true) => {
+                replaced += 1;
+                ("~".yellow(), "overwritten".yellow(), true)
+            }
+            (true, false) => {
+                skipped += 1;
+                let why = if overwrite { "locked, skipped" } else { "exists, skipped" };
+                ("·".dimmed(), why.dimmed(), false)
+            }
+        };
+        println!("  {} {}  {}  {}", marker, name.cyan(), status, summary.dimmed());
+        
+        if write && !dry_run && save_and_apply(&pm, name, data)? {
+            println!("    {} '{}' is current, so settings.json was updated too", "ℹ".blue(), name);
+        }
+    }
+    
+    let verb = if dry_run { "Would create" } else { "Created" };
+    println!(
+        "{} {} {}, {} {}, skipped {}",
+        if dry_run { "ℹ".blue() } else { "✓".green() },
+        verb,
+        created,
+        if dry_run { "overwrite" } else { "overwrote" },
+        replaced,
+        skipped,
+    );
+    Ok(())
+}
+
+/// Values for `--vary`: a comma-separated list, or `@file` with one value
+/// per line (blank lines and `#` comments are ignored).
+fn read_variants(spec: &str) -> Result<Vec<String>> {
+    match spec.strip_prefix('@') {
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path))?;
+            Ok(content.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_string)
+                .collect())
+        }
+        None => Ok(spec.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()),
+    }
+}
+
+/// Fill `{base}` and `{key}` placeholders in a `generate --name` pattern. A
+/// key may be given as its full path or its last segment. Values are reduced
+/// to characters allowed in profile names.
+fn render_name(pattern: &str, base: &str, combo: &[(&str, &str)]) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .with_context(|| format!("Unclosed '{{' in name pattern '{}'", pattern))?;
+        let placeholder = &rest[start + 1..start + end];
+        let value = if placeholder == "base" {
+            base.to_string()
+        } else {
+            let (_, value) = combo.iter()
+                .find(|(key, _)| *key == placeholder || key.rsplit('.').next() == Some(placeholder))
+                .with_context(|| format!("Unknown placeholder '{{{}}}' in name pattern", placeholder))?;
+            name_safe(value)
+        };
+        name.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
+
+/// `https://api.example.com/v1` → `api.example.com-v1`
+fn name_safe(value: &str) -> String {
+    let value = value.split_once("://").map_or(value, |(_, rest)| rest);
+    let mut out = String::new();
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+            out.push(c);
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_matches(|c| c == '-' || c == '.').to_string()
+}
+
+pub fn alias_set(alias: &str, profile: &str) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    validate_profile_name(alias)?;
+    
+    if pm.profile_exists(alias) {
+        bail!("'{}' is already a profile name and cannot be used as an alias", alias);
+    }
+    let target = pm.resolve_name(profile)?;
+    
+    let mut aliases = pm.load_aliases()?;
+    aliases.insert(alias.to_string(), target.clone());
+    pm.save_aliases(&aliases)?;
+    
+    println!("{} Alias '{}' → '{}'", "✓".green(), alias.cyan(), target);
+    Ok(())
+}
+
+pub fn alias_remove(alias: &str) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let mut aliases = pm.load_aliases()?;
+    
+    if aliases.remove(alias).is_none() {
+        bail!("Alias '{}' does not exist", alias);
+    }
+    pm.save_aliases(&aliases)?;
+    
+    println!("{} Removed alias '{}'", "✓".green(), alias);
+    Ok(())
+}
+
+pub fn alias_list() -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let aliases = pm.load_aliases()?;
+    
+    if aliases.is_empty() {
+        println!("{}", "No aliases defined. Use 'ccp alias set <alias> <profile>'.".yellow());
+        return Ok(());
+    }
+    
+    println!("{}", "Aliases:".bold());
+    for (alias, target) in &aliases {
+        let missing = if pm.profile_exists(target) { "".normal() } else { " (missing)".red() };
+        println!("  {} → {}{}", alias.cyan(), target, missing);
+    }
+    Ok(())
+}
+
+pub fn export(name: Option<&str>, format: Format, tags: &[String], resolved: bool) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let load = |profile: &str| -> Result<serde_json::Value> {
+        let data = pm.load_profile(profile)?;
+        if resolved {
+            pm.resolve_profile(&data).with_context(|| format!("Cannot resolve '{}'", profile))
+        } else {
+            Ok(data)
+        }
+    };
+    
+    if !tags.is_empty() {
+        let profiles = pm.profiles_with_tags(tags)?;
+        if profiles.is_empty() {
+            bail!("No profiles tagged {}", tags.join(", "));
+        }
+        
+        let mut bundle = serde_json::Map::new();
+        for profile in &profiles {
+            bundle.insert(profile.clone(), load(profile)?);
+        }
+        let output = format.to_string(&serde_json::json!({ "profiles": bundle }))?;
+        println!("{}", output.trim_end());
+        eprintln!("{} Exported {} profile(s)", "✓".green(), profiles.len());
+        return Ok(());
+    }
+    
+    let profile_name = target_profile(&pm, name)?;
+    
+    let data = load(&profile_name)?;
+    let output = format.to_string(&data)?;
+    println!("{}", output.trim_end());
+    
+    Ok(())
+}
+
+pub fn import(name: Option<&str>, format: Format, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    if let Some(name) = name {
+        validate_profile_name(name)?;
+    }
+    pm.config.ensure_dirs()?;
+    
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)
+        .context("Failed to read from stdin")?;
+    
+    let data = format.parse(&input)
+        .context("Failed to parse profile from stdin")?;
+    
+    let Some(name) = name else {
+        return import_bundle(&pm, &data, format, force);
+    };
+    
+    check_importable(&pm, name, force)?;
+    confirm_generators(&[(name, &data)], force)?;
+    pm.replace_profile(name, &data, format)?;
+    
+    eprintln!("{} Imported profile '{}'", "✓".green(), name.cyan());
+    Ok(())
+}
+
+/// Import every profile of a bundle written by `export --tag`. Nothing is
+/// written unless all names are valid and free.
+fn import_bundle(pm: &ProfileManager, data: &serde_json::Value, format: Format, force: bool) -> Result<()> {
+    let profiles = data.get("profiles")
+        .and_then(|p| p.as_object())
+        .context("Input is not a profile bundle (expected a 'profiles' object). Pass a name to import a single profile.")?;
+    
+    for name in profiles.keys() {
+        validate_profile_name(name)?;
+        check_importable(pm, name, force)?;
+    }
+    let imported: Vec<_> = profiles.iter().map(|(name, profile)| (name.as_str(), profile)).collect();
+    confirm_generators(&imported, force)?;
+    
+    for (name, profile) in profiles {
+        pm.replace_profile(name, profile, format)?;
+        eprintln!("{} Imported profile '{}'", "✓".green(), name.cyan());
+    }
+    Ok(())
+}
+
+/// A `generator` runs a shell command whenever the profile is applied, so
+/// importing one needs `--force` or an explicit confirmation.
+fn confirm_generators(profiles: &[(&str, &serde_json::Value)], force: bool) -> Result<()> {
+    let generators: Vec<_> = profiles.iter()
+        .filter_map(|(name, data)| Some((*name, data.get("generator")?)))
+        .collect();
+    if generators.is_empty() || force {
+        return Ok(());
+    }
+    
+    eprintln!("{} Imported profiles run commands when applied:", "!".yellow());
+    for (name, generator) in &generators {
+        let command = generator.as_str()
+            .or_else(|| generator.get("command").and_then(|c| c.as_str()))
+            .map(str::to_string)
+            .unwrap_or_else(|| generator.to_string());
+        eprintln!("  {} {}", name.cyan(), command);
+    }
+    
+    // stdin carries the import itself, so only a terminal on stderr can answer
+    if !io::stderr().is_terminal() {
+        bail!("Refusing to import a profile with a generator. Use --force if you trust it.");
+    }
+    let confirm = Confirm::new()
+        .with_prompt("Import anyway?")
+        .default(false)
+        .interact()?;
+    if !confirm {
+        bail!("Import cancelled");
+    }
+    Ok(())
+}
+
+/// An import may only replace an existing profile with `--force`.
+fn check_importable(pm: &ProfileManager, name: &str, force: bool) -> Result<()> {
+    if pm.profile_exists(name) && !force {
+        pm.check_unlocked(name)?;
+        bail!("Profile '{}' already exists. Use --force to overwrite it or choose a different name.", name);
+    }
+    Ok(())
+}
+
+pub fn diff(profile1: &str, profile2: &str) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let profile1 = &target_profile(&pm, Some(profile1))?;
+    let profile2 = &target_profile(&pm, Some(profile2))?;
+    
+    let data1 = pm.load_profile(profile1)?;
+    let data2 = pm.load_profile(profile2)?;
+    
+    let json1 = serde_json::to_string_pretty(&data1)?;
+    let json2 = serde_json::to_string_pretty(&data2)?;
+    
+    if json1 == json2 {
+        println!("{} Profiles are identical", "=".green());
+        return Ok(());
+    }
+    
+    println!("{} {} vs {}", "Diff:".bold(), profile1.red(), profile2.green());
+    println!();
+    
+    let diff = TextDiff::from_lines(&json1, &json2);
     
     for change in diff.iter_all_changes() {
         let line = change.value();
@@ -508,64 +1726,701 @@ pub fn diff(profile1: &str, profile2: &str) -> Result<()> {
 
 pub fn backup(name: Option<&str>) -> Result<()> {
     let pm = ProfileManager::new()?;
-    pm.config.ensure_dirs()?;
-    
-    if !pm.config.settings_file.exists() {
-        bail!("No settings.json found to backup");
-    }
     
     let backup_name = match name {
         Some(n) => n.to_string(),
         None => Local::now().format("backup-%Y%m%d-%H%M%S").to_string(),
     };
+    validate_name("Backup", &backup_name)?;
+    pm.config.ensure_dirs()?;
+    
+    if !pm.config.settings_file.exists() {
+        bail!("No settings.json found to backup");
+    }
     
     let data = pm.load_settings()?;
-    pm.save_backup(&backup_name, &data)?;
+    pm.save_backup(&backup_name, &data, BackupOrigin::Manual)?;
     
     println!("{} Created backup '{}'", "✓".green(), backup_name.cyan());
     println!("  Path: {}", pm.config.backup_path(&backup_name).display());
+    auto_prune(&pm, None)
+}
+
+/// Delete the backups `retention` doesn't keep. Named backups only count
+/// with `include_named`. Returns the deleted (or, on a dry run, doomed) names.
+fn prune_backups(
+    pm: &ProfileManager,
+    retention: &Retention,
+    prefix: Option<&str>,
+    include_named: bool,
+    dry_run: bool,
+    keep: Option<&str>,
+) -> Result<Vec<String>> {
+    let candidates: Vec<_> = pm.list_backup_metas()?
+        .into_iter()
+        .filter(|(name, _)| prefix.is_none_or(|p| name.starts_with(p)))
+        .filter(|(name, _)| keep != Some(name.as_str()))
+        .filter(|(name, _)| include_named || meta::is_generated_backup_name(name))
+        .map(|(name, meta)| (name, meta.created))
+        .collect();
+    
+    let doomed = retention.select(&candidates, Local::now())?;
+    if !dry_run {
+        for name in &doomed {
+            pm.delete_backup(name)?;
+        }
+    }
+    Ok(doomed)
+}
+
+/// Apply the configured retention after a new backup, sparing `keep` (the
+/// backup being restored).
+fn auto_prune(pm: &ProfileManager, keep: Option<&str>) -> Result<()> {
+    let Some(retention) = pm.load_prefs()?.backup_retention else {
+        return Ok(());
+    };
+    let pruned = prune_backups(pm, &retention, None, false, false, keep)?;
+    if !pruned.is_empty() {
+        eprintln!("{} Pruned {} old backup(s) ({})", "ℹ".blue(), pruned.len(), retention.describe());
+    }
+    Ok(())
+}
+
+pub fn backup_list() -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let mut backups = pm.list_backup_metas()?;
+    
+    if backups.is_empty() {
+        println!("{}", "No backups yet. Create one with 'ccp backup'.".yellow());
+        return Ok(());
+    }
+    backups.sort_by_key(|(_, meta)| std::cmp::Reverse(meta.created));
+    
+    let width = backups.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    println!("{}", "Backups (newest first):".bold());
+    for (name, meta) in &backups {
+        let size = fs::metadata(pm.config.backup_path(name)).map(|m| m.len()).unwrap_or(0);
+        println!(
+            "  {:<width$}  {}  {:>8}  {:<11}  {}",
+            name.cyan(),
+            format!("{} ({})", meta.created.format("%Y-%m-%d %H:%M"), relative_time(meta.created)).dimmed(),
+            format_size(size),
+            meta.origin.to_string(),
+            meta.profile.as_deref().unwrap_or("-"),
+            width = width,
+        );
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024 => format!("{} B", b),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+pub fn backup_show(name: &str) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    validate_name("Backup", name)?;
+    if !pm.backup_exists(name) {
+        bail!("Backup '{}' not found. Use 'ccp backup list' to see available backups.", name);
+    }
+    
+    let meta = pm.load_backup_meta(name)?;
+    println!("{}", name.bold());
+    println!("  Created: {} ({})", meta.created.format("%Y-%m-%d %H:%M:%S"), relative_time(meta.created));
+    println!("  Origin:  {}", meta.origin);
+    println!("  Profile: {}", meta.profile.as_deref().unwrap_or("-"));
+    println!();
+    println!("{}", serde_json::to_string_pretty(&redact(&pm.load_backup(name)?))?);
+    Ok(())
+}
+
+/// Hide values of keys that look like credentials, keeping the last few
+/// characters of long ones so they can still be told apart.
+fn redact(value: &serde_json::Value) -> serde_json::Value {
+    const SECRET_WORDS: [&str; 6] = ["KEY", "TOKEN", "SECRET", "PASSWORD", "CREDENTIAL", "AUTH"];
+    match value {
+        serde_json::Value::Object(map) => map.iter()
+            .map(|(key, v)| {
+                let upper = key.to_uppercase();
+                let secret = SECRET_WORDS.iter().any(|w| upper.contains(w));
+                let v = match v {
+                    serde_json::Value::String(s) if secret && !s.is_empty() => {
+                        let tail: String = s.chars().skip(s.chars().count().saturating_sub(4)).collect();
+                        if s.chars().count() >= 12 {
+                            serde_json::Value::String(format!("****{}", tail))
+                        } else {
+                            serde_json::Value::String("****".to_string())
+                        }
+                    }
+                    other => redact(other),
+                };
+                (key.clone(), v)
+            })
+            .collect(),
+        serde_json::Value::Array(items) => items.iter().map(redact).collect(),
+        other => other.clone(),
+    }
+}
+
+pub fn backup_delete(name: &str, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    validate_name("Backup", name)?;
+    if !pm.backup_exists(name) {
+        bail!("Backup '{}' not found. Use 'ccp backup list' to see available backups.", name);
+    }
+    
+    if !force {
+        let confirm = Confirm::new()
+            .with_prompt(format!("Delete backup '{}'?", name))
+            .default(false)
+            .interact()?;
+        if !confirm {
+            println!("Cancelled");
+            return Ok(());
+        }
+    }
+    
+    pm.delete_backup(name)?;
+    println!("{} Deleted backup '{}'", "✓".green(), name);
     Ok(())
 }
 
-pub fn restore(backup: &str) -> Result<()> {
+pub fn backup_prune(
+    keep: Option<usize>,
+    older_than: Option<String>,
+    prefix: Option<&str>,
+    include_named: bool,
+    dry_run: bool,
+) -> Result<()> {
     let pm = ProfileManager::new()?;
     
-    // Check if it's a backup or profile
-    let data = if pm.config.backup_path(backup).exists() {
-        pm.load_backup(backup)?
-    } else if pm.profile_exists(backup) {
-        pm.load_profile(backup)?
+    let retention = if keep.is_none() && older_than.is_none() {
+        pm.load_prefs()?.backup_retention
+            .context("Give --keep and/or --older-than, or set a default with 'ccp backup retention'")?
+    } else {
+        Retention { keep, older_than }
+    };
+    
+    let doomed = prune_backups(&pm, &retention, prefix, include_named, dry_run, None)?;
+    if doomed.is_empty() {
+        println!("{} Nothing to prune", "·".dimmed());
+        return Ok(());
+    }
+    for name in &doomed {
+        println!("  {} {}", "-".red(), name);
+    }
+    if dry_run {
+        println!("{} Would delete {} backup(s)", "ℹ".blue(), doomed.len());
     } else {
-        // List available backups
+        println!("{} Deleted {} backup(s)", "✓".green(), doomed.len());
+    }
+    Ok(())
+}
+
+pub fn backup_retention(keep: Option<usize>, older_than: Option<String>, off: bool) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let mut prefs = pm.load_prefs()?;
+    
+    if off {
+        prefs.backup_retention = None;
+        pm.save_prefs(&prefs)?;
+        println!("{} Automatic pruning disabled", "✓".green());
+        return Ok(());
+    }
+    
+    if keep.is_none() && older_than.is_none() {
+        match &prefs.backup_retention {
+            Some(retention) => println!("Backup retention: {}", retention.describe()),
+            None => println!("{}", "No backup retention set; backups are kept forever.".dimmed()),
+        }
+        return Ok(());
+    }
+    
+    if let Some(age) = &older_than {
+        prefs::parse_age(age)?;
+    }
+    let retention = Retention { keep, older_than };
+    println!("{} Backup retention: {}", "✓".green(), retention.describe());
+    println!("  Applied after every backup; named backups are never pruned automatically.");
+    prefs.backup_retention = Some(retention);
+    pm.save_prefs(&prefs)
+}
+
+/// Restore settings.json from a backup, or from a profile with
+/// `from_profile`. With `to_profile`, the backup is saved as that profile
+/// instead, and only applied if `switch` is set. Without a name, the backup
+/// is picked interactively.
+pub fn restore(
+    name: Option<&str>,
+    from_backup: bool,
+    from_profile: bool,
+    to_profile: Option<&str>,
+    switch: bool,
+    force: bool,
+) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    
+    let name = match name {
+        Some(name) => name.to_string(),
+        None if from_profile => bail!("Give the profile to restore from, e.g. 'ccp restore work --profile'"),
+        None => match pick_backup(&pm)? {
+            Some(name) => name,
+            None => {
+                println!("Cancelled");
+                return Ok(());
+            }
+        },
+    };
+    let name = name.as_str();
+    
+    if from_profile {
+        let profile = pm.resolve_name(name)?;
+        let data = pm.resolve_profile(&pm.load_profile(&profile)?)?;
+        pre_restore_backup(&pm, None)?;
+        pm.save_settings(&data)?;
+        pm.set_current_profile(&profile)?;
+        println!("{} Restored settings from profile '{}'", "✓".green(), profile.cyan());
+        return Ok(());
+    }
+    
+    validate_name("Backup", name)?;
+    if !pm.backup_exists(name) {
+        if !from_backup && pm.profile_exists(name) {
+            bail!("Backup '{}' not found. To restore the profile of that name, use 'ccp restore {} --profile'.", name, name);
+        }
         let backups = pm.list_backups()?;
         if backups.is_empty() {
-            bail!("Backup '{}' not found and no backups available", backup);
-        } else {
-            println!("{}", "Available backups:".bold());
-            for b in &backups {
-                println!("  {}", b);
+            bail!("Backup '{}' not found and no backups available", name);
+        }
+        println!("{}", "Available backups:".bold());
+        for b in &backups {
+            println!("  {}", b);
+        }
+        bail!("Backup '{}' not found", name);
+    }
+    let data = pm.load_backup(name)?;
+    let recorded = pm.load_backup_meta(name)?.profile;
+    
+    if let Some(target) = to_profile {
+        validate_profile_name(target)?;
+        if pm.profile_exists(target) {
+            pm.check_unlocked(target)?;
+            if !force {
+                bail!("Profile '{}' already exists. Use --force to overwrite it.", target);
             }
-            bail!("Backup '{}' not found", backup);
         }
+        pm.config.ensure_dirs()?;
+        let is_current = pm.get_current_profile()?.as_deref() == Some(target);
+        if switch || is_current {
+            pre_restore_backup(&pm, Some(name))?;
+        }
+        // A current profile is re-applied so settings.json keeps matching it
+        save_and_apply(&pm, target, &data)?;
+        println!("{} Saved backup '{}' as profile '{}'", "✓".green(), name, target.cyan());
+        if is_current {
+            println!("{} '{}' is the current profile, so settings.json was updated too", "ℹ".blue(), target);
+        } else if switch {
+            pm.apply_profile(&data)?;
+            pm.set_current_profile(target)?;
+            println!("{} Switched to profile '{}'", "✓".green(), target.cyan());
+        }
+        return Ok(());
+    }
+    
+    pre_restore_backup(&pm, Some(name))?;
+    pm.save_settings(&data)?;
+    println!("{} Restored from '{}'", "✓".green(), name.cyan());
+    
+    // Keep .current truthful: point it at a profile that produces exactly
+    // these settings, or clear it
+    let previous = pm.get_current_profile()?;
+    match matching_profile(&pm, &data, &[recorded, previous.clone()])? {
+        Some(profile) => {
+            pm.set_current_profile(&profile)?;
+            if previous.as_deref() != Some(profile.as_str()) {
+                println!("{} Current profile is now '{}', which matches the restored settings", "ℹ".blue(), profile.cyan());
+            }
+        }
+        None => {
+            pm.clear_current_profile()?;
+            if let Some(previous) = previous {
+                println!(
+                    "{} settings.json no longer matches profile '{}' or any other; no profile is current now.",
+                    "!".yellow(),
+                    previous,
+                );
+            } else {
+                println!("{} settings.json does not match any profile.", "!".yellow());
+            }
+            println!("  Keep it as a profile with 'ccp restore {} --to-profile <name>'.", name);
+        }
+    }
+    Ok(())
+}
+
+/// Let the user choose a backup, newest first, previewing how each would
+/// change settings.json before confirming.
+fn pick_backup(pm: &ProfileManager) -> Result<Option<String>> {
+    if !io::stdin().is_terminal() {
+        bail!("No backup given. Pass a backup name, or run in a terminal to pick one.");
+    }
+    let mut backups = pm.list_backup_metas()?;
+    if backups.is_empty() {
+        bail!("No backups available. Create one with 'ccp backup'.");
+    }
+    backups.sort_by_key(|(_, meta)| std::cmp::Reverse(meta.created));
+    
+    let width = backups.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let items: Vec<String> = backups.iter()
+        .map(|(name, meta)| {
+            format!(
+                "{:<width$}  {}",
+                name,
+                format!("{} · {} · {}", relative_time(meta.created), meta.origin, meta.profile.as_deref().unwrap_or("-")).dimmed(),
+                width = width,
+            )
+        })
+        .collect();
+    let current = if pm.config.settings_file.exists() {
+        pm.load_settings()?
+    } else {
+        serde_json::json!({})
     };
     
-    // Create backup of current before restoring
+    let mut default_idx = 0;
+    loop {
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Restore which backup?")
+            .items(&items)
+            .default(default_idx)
+            .interact_opt()?;
+        let Some(idx) = selection else {
+            return Ok(None);
+        };
+        let name = &backups[idx].0;
+        
+        println!("{} {}", "Changes to settings.json from".bold(), name.cyan());
+        if !print_key_diff(&current, &pm.load_backup(name)?) {
+            println!("  {}", "No changes; the backup matches the current settings".dimmed());
+        }
+        
+        let confirm = Confirm::new()
+            .with_prompt(format!("Restore '{}'?", name))
+            .default(false)
+            .interact()?;
+        if confirm {
+            return Ok(Some(name.clone()));
+        }
+        default_idx = idx;
+    }
+}
+
+/// Print how `after` differs from `before`, one line per changed key, with
+/// credentials redacted. Returns whether anything differs.
+fn print_key_diff(before: &serde_json::Value, after: &serde_json::Value) -> bool {
+    let flatten = |value: &serde_json::Value| -> BTreeMap<String, serde_json::Value> {
+        conditions::leaves(&redact(value), "").into_iter()
+            .filter(|(path, _)| !path.is_empty())
+            .collect()
+    };
+    let before = flatten(before);
+    let after = flatten(after);
+    
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let mut changed = false;
+    for key in keys {
+        let line = match (before.get(key), after.get(key)) {
+            (Some(old), None) => format!("- {}: {}", key, old).red(),
+            (None, Some(new)) => format!("+ {}: {}", key, new).green(),
+            (Some(old), Some(new)) if old != new => format!("~ {}: {} → {}", key, old, new).yellow(),
+            _ => continue,
+        };
+        println!("  {}", line);
+        changed = true;
+    }
+    changed
+}
+
+/// Back up settings.json before it is overwritten by a restore.
+fn pre_restore_backup(pm: &ProfileManager, restoring: Option<&str>) -> Result<()> {
     if pm.config.settings_file.exists() {
         let auto_backup = Local::now().format("pre-restore-%Y%m%d-%H%M%S").to_string();
         let current = pm.load_settings()?;
-        pm.save_backup(&auto_backup, &current)?;
+        pm.save_backup(&auto_backup, &current, BackupOrigin::PreRestore)?;
         eprintln!("{} Created auto-backup '{}'", "ℹ".blue(), auto_backup);
+        auto_prune(pm, restoring)?;
+    }
+    Ok(())
+}
+
+/// The profile whose resolved settings equal `settings`, trying `preferred`
+/// names first. Generated profiles are skipped so no command runs here.
+fn matching_profile(
+    pm: &ProfileManager,
+    settings: &serde_json::Value,
+    preferred: &[Option<String>],
+) -> Result<Option<String>> {
+    let mut candidates: Vec<String> = preferred.iter().flatten().cloned().collect();
+    candidates.extend(pm.list_profiles()?);
+    
+    for name in candidates {
+        if !pm.profile_exists(&name) {
+            continue;
+        }
+        let Ok(data) = pm.load_profile(&name) else { continue };
+        if data.get("generator").is_some() {
+            continue;
+        }
+        if pm.resolve_profile(&data).is_ok_and(|resolved| &resolved == settings) {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
+
+pub fn snapshot_create(name: Option<&str>) -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => Local::now().format("snapshot-%Y%m%d-%H%M%S").to_string(),
+    };
+    validate_name("Snapshot", &name)?;
+    let path = pm.config.snapshot_path(&name);
+    if path.exists() {
+        bail!("Snapshot '{}' already exists", name);
     }
     
-    pm.save_settings(&data)?;
+    let snapshot = Snapshot::capture(&pm.config)?;
+    snapshot.save(&path)?;
+    println!(
+        "{} Created snapshot '{}' ({} profiles, {} files)",
+        "✓".green(),
+        name.cyan(),
+        snapshot.profiles().len(),
+        snapshot.files.len(),
+    );
+    Ok(())
+}
+
+pub fn snapshot_list() -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let mut snapshots = Vec::new();
+    if pm.config.snapshots_dir.exists() {
+        for entry in fs::read_dir(&pm.config.snapshots_dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_stem().filter(|_| path.extension().is_some_and(|e| e == "json")) else {
+                continue;
+            };
+            let name = name.to_string_lossy().to_string();
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            snapshots.push((name, size, Snapshot::load(&path)?));
+        }
+    }
+    
+    if snapshots.is_empty() {
+        println!("{}", "No snapshots yet. Create one with 'ccp snapshot create'.".yellow());
+        return Ok(());
+    }
+    snapshots.sort_by_key(|(_, _, snapshot)| std::cmp::Reverse(snapshot.created));
+    
+    let width = snapshots.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+    println!("{}", "Snapshots (newest first):".bold());
+    for (name, size, snapshot) in &snapshots {
+        println!(
+            "  {:<width$}  {}  {:>8}  {:>3} profiles  {}",
+            name.cyan(),
+            format!("{} ({})", snapshot.created.format("%Y-%m-%d %H:%M"), relative_time(snapshot.created)).dimmed(),
+            format_size(*size),
+            snapshot.profiles().len(),
+            snapshot.current_profile().as_deref().unwrap_or("-"),
+            width = width,
+        );
+    }
+    Ok(())
+}
+
+pub fn snapshot_restore(name: &str, yes: bool, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    validate_name("Snapshot", name)?;
+    let path = pm.config.snapshot_path(name);
+    if !path.exists() {
+        bail!("Snapshot '{}' not found. Use 'ccp snapshot list' to see available snapshots.", name);
+    }
+    
+    let target = Snapshot::load(&path)?;
+    let current = Snapshot::capture(&pm.config)?;
+    let summary = current.summary(&target);
+    if current.same_state(&target) {
+        println!("{} Nothing to restore; current state matches '{}'", "·".dimmed(), name);
+        return Ok(());
+    }
+    check_changes_unlocked(&pm, &journal::changes(&current, &target))?;
     
-    println!("{} Restored from '{}'", "✓".green(), backup.cyan());
+    println!("Restoring '{}' ({}):", name.cyan(), relative_time(target.created));
+    print_state_changes(&summary);
+    
+    if !yes {
+        let confirm = Confirm::new()
+            .with_prompt("Restore this snapshot?")
+            .default(false)
+            .interact()?;
+        if !confirm {
+            println!("Cancelled");
+            return Ok(());
+        }
+    }
+    
+    // Keep the state being replaced, so the restore itself can be undone
+    let safety = Local::now().format("pre-restore-%Y%m%d-%H%M%S").to_string();
+    current.save(&pm.config.snapshot_path(&safety))?;
+    eprintln!("{} Saved current state as snapshot '{}'", "ℹ".blue(), safety);
+    
+    target.restore(&pm.config)?;
+    println!("{} Restored snapshot '{}'", "✓".green(), name.cyan());
+    Ok(())
+}
+
+/// Print what going from one state to another changes, profile by profile.
+fn print_state_changes(summary: &snapshot::Summary) {
+    for profile in &summary.added {
+        println!("  {} {}", "+".green(), profile);
+    }
+    for profile in &summary.removed {
+        println!("  {} {}", "-".red(), profile);
+    }
+    for profile in &summary.changed {
+        println!("  {} {}", "~".yellow(), profile);
+    }
+    if let Some((from, to)) = &summary.current {
+        println!(
+            "  Current profile: {} → {}",
+            from.as_deref().unwrap_or("none"),
+            to.as_deref().unwrap_or("none"),
+        );
+    }
+    if summary.settings_changed {
+        println!("  settings.json changes");
+    }
+    if summary.added.is_empty() && summary.removed.is_empty() && summary.changed.is_empty() {
+        println!("  {}", "No profile changes; only metadata differs".dimmed());
+    }
+}
+
+/// Capture the state before a journaled command. The journal is a safety
+/// net, so a failure here only warns.
+pub fn journal_begin(label: String) -> Option<(String, Snapshot)> {
+    let pm = ProfileManager::new().ok()?;
+    if pm.load_prefs().ok()?.journal_limit() == 0 {
+        return None;
+    }
+    match Snapshot::capture_state(&pm.config) {
+        Ok(before) => Some((label, before)),
+        Err(e) => {
+            eprintln!("{} This change cannot be undone: {:#}", "!".yellow(), e);
+            None
+        }
+    }
+}
+
+/// Journal a command if it changed anything, including one that failed
+/// part way, so whatever it did write can be undone.
+pub fn journal_record(label: String, before: Snapshot, failed: bool) {
+    let result = (|| -> Result<()> {
+        let pm = ProfileManager::new()?;
+        let changes = journal::changes(&before, &Snapshot::capture_state(&pm.config)?);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let entry = journal::Entry {
+            time: Local::now(),
+            summary: if failed { format!("{} (failed)", label) } else { label },
+            changes,
+            undone: false,
+        };
+        Journal::new(&pm.config).record(&entry, pm.load_prefs()?.journal_limit())
+    })();
+    if let Err(e) = result {
+        eprintln!("{} This change cannot be undone: {:#}", "!".yellow(), e);
+    }
+}
+
+pub fn undo(steps: usize, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let journal = Journal::new(&pm.config);
+    let start = Snapshot::capture(&pm.config)?;
+    let picked = journal.undoable(steps)?;
+    check_changes_unlocked(&pm, picked.iter().flat_map(|(_, entry)| &entry.changes))?;
+    
+    for entry in journal.undo(&pm.config, picked, force)? {
+        println!(
+            "{} Undid: {} {}",
+            "✓".green(),
+            entry.summary,
+            format!("({})", relative_time(entry.time)).dimmed(),
+        );
+    }
+    print_state_changes(&start.summary(&Snapshot::capture(&pm.config)?));
+    println!("Redo with 'ccp redo'.");
+    Ok(())
+}
+
+pub fn redo(steps: usize, force: bool) -> Result<()> {
+    let pm = ProfileManager::new()?.force(force);
+    let journal = Journal::new(&pm.config);
+    let start = Snapshot::capture(&pm.config)?;
+    let picked = journal.redoable(steps)?;
+    check_changes_unlocked(&pm, picked.iter().flat_map(|(_, entry)| &entry.changes))?;
+    
+    for entry in journal.redo(&pm.config, picked, force)? {
+        println!("{} Redid: {} {}", "✓".green(), entry.summary, format!("({})", relative_time(entry.time)).dimmed());
+    }
+    print_state_changes(&start.summary(&Snapshot::capture(&pm.config)?));
+    Ok(())
+}
+
+/// Refuse changes to the files or metadata of locked profiles, unless the
+/// manager is forced.
+fn check_changes_unlocked<'a>(pm: &ProfileManager, changes: impl IntoIterator<Item = &'a journal::Change>) -> Result<()> {
+    let profiles: BTreeSet<String> = changes.into_iter()
+        .filter_map(journal::Change::profile)
+        .filter(|profile| validate_profile_name(profile).is_ok())
+        .collect();
+    for profile in &profiles {
+        pm.check_unlocked(profile)?;
+    }
+    Ok(())
+}
+
+pub fn journal() -> Result<()> {
+    let pm = ProfileManager::new()?;
+    let entries = Journal::new(&pm.config).entries()?;
+    
+    if entries.is_empty() {
+        println!("{}", "Nothing to undo yet.".yellow());
+        return Ok(());
+    }
+    
+    println!("{}", "Journal (newest first):".bold());
+    for (_, entry) in entries.iter().rev() {
+        let line = format!("{:<16}  {}", relative_time(entry.time), entry.summary);
+        if entry.is_undone() {
+            println!("  {} {}", "↷".dimmed(), format!("{} (undone)", line).dimmed());
+        } else {
+            println!("  {} {}", "·".dimmed(), line);
+        }
+    }
+    println!();
+    println!("Undo the newest change with 'ccp undo', redo an undone one with 'ccp redo'.");
     Ok(())
 }
 
 pub fn completions(shell: Shell) -> Result<()> {
     let mut cmd = Cli::command();
     let name = cmd.get_name().to_string();
-    generate(shell, &mut cmd, name, &mut io::stdout());
+    clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
     Ok(())
 }
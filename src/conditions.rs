@@ -0,0 +1,177 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+
+/// A `when` block: settings that only apply on machines matching every
+/// condition given.
+///
+/// ```json
+/// "when": [
+///   { "hostname": "office-*", "settings": { "env": { "HTTPS_PROXY": "http://proxy:3128" } } }
+/// ]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WhenBlock {
+    /// Glob matched against the machine's hostname
+    pub hostname: Option<String>,
+    /// Glob matched against the OS user name
+    pub user: Option<String>,
+    /// `VAR` (variable is set) or `VAR=value`
+    pub env: Option<String>,
+    pub settings: Value,
+}
+
+/// The facts `when` conditions are tested against.
+pub struct Machine {
+    pub hostname: String,
+    pub user: String,
+    pub env: BTreeMap<String, String>,
+}
+
+impl Machine {
+    pub fn current() -> Self {
+        Self {
+            hostname: gethostname::gethostname().to_string_lossy().to_string(),
+            user: env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_default(),
+            env: env::vars().collect(),
+        }
+    }
+}
+
+impl WhenBlock {
+    pub fn matches(&self, machine: &Machine) -> bool {
+        let glob = |pattern: &Option<String>, value: &str| match pattern {
+            Some(pattern) => glob::Pattern::new(pattern).is_ok_and(|p| p.matches(value)),
+            None => true,
+        };
+        let env_ok = match self.env.as_deref() {
+            None => true,
+            Some(condition) => match condition.split_once('=') {
+                Some((name, value)) => machine.env.get(name).is_some_and(|v| v == value),
+                None => machine.env.contains_key(condition),
+            },
+        };
+        glob(&self.hostname, &machine.hostname) && glob(&self.user, &machine.user) && env_ok
+    }
+
+    /// The block's conditions, e.g. `hostname=office-*, env=CI`.
+    pub fn describe(&self) -> String {
+        let conditions: Vec<String> = [("hostname", &self.hostname), ("user", &self.user), ("env", &self.env)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|v| format!("{}={}", name, v)))
+            .collect();
+        if conditions.is_empty() {
+            "always".to_string()
+        } else {
+            conditions.join(", ")
+        }
+    }
+}
+
+/// Parse a profile's `when` value.
+pub fn parse_blocks(value: &Value) -> Result<Vec<WhenBlock>> {
+    let blocks: Vec<WhenBlock> = serde_json::from_value(value.clone())
+        .context("'when' must be a list of blocks with 'hostname', 'user', 'env' and 'settings'")?;
+    for (i, block) in blocks.iter().enumerate() {
+        if !block.settings.is_object() {
+            bail!("'when[{}].settings' must be an object", i);
+        }
+        for pattern in [&block.hostname, &block.user].into_iter().flatten() {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("Invalid pattern '{}' in 'when[{}]'", pattern, i))?;
+        }
+    }
+    Ok(blocks)
+}
+
+/// Take the `when` blocks out of a profile and merge the matching ones in,
+/// in order. Returns the indices of the blocks that matched.
+pub fn apply(data: &mut Value, machine: &Machine) -> Result<Vec<usize>> {
+    let Some(when) = data.as_object_mut().and_then(|m| m.shift_remove("when")) else {
+        return Ok(Vec::new());
+    };
+    let mut matched = Vec::new();
+    for (i, block) in parse_blocks(&when)?.into_iter().enumerate() {
+        if block.matches(machine) {
+            deep_merge(data, &block.settings);
+            matched.push(i);
+        }
+    }
+    Ok(matched)
+}
+
+/// Merge `overlay` into `base`: objects are merged key by key, anything else
+/// is replaced.
+pub fn deep_merge(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+/// Every non-object value under `value` with its dotted key path.
+pub fn leaves(value: &Value, path: &str) -> Vec<(String, Value)> {
+    match value {
+        Value::Object(map) if !map.is_empty() => map.iter()
+            .flat_map(|(key, v)| {
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                leaves(v, &child)
+            })
+            .collect(),
+        _ => vec![(path.to_string(), value.clone())],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn machine() -> Machine {
+        Machine {
+            hostname: "office-42".to_string(),
+            user: "alice".to_string(),
+            env: BTreeMap::from([("CI".to_string(), "true".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_apply_merges_matching_blocks_in_order() {
+        let mut data = json!({
+            "model": "sonnet",
+            "env": { "A": "1", "HTTPS_PROXY": "none" },
+            "when": [
+                { "hostname": "office-*", "settings": { "env": { "HTTPS_PROXY": "http://proxy:3128" } } },
+                { "hostname": "laptop-*", "settings": { "model": "haiku" } },
+                { "user": "alice", "env": "CI=true", "settings": { "env": { "B": "2" } } },
+                { "env": "MISSING", "settings": { "model": "opus" } },
+            ],
+        });
+        let matched = apply(&mut data, &machine()).unwrap();
+
+        assert_eq!(matched, [0, 2]);
+        assert_eq!(data, json!({
+            "model": "sonnet",
+            "env": { "A": "1", "HTTPS_PROXY": "http://proxy:3128", "B": "2" },
+        }));
+    }
+
+    #[test]
+    fn test_parse_blocks_rejects_bad_input() {
+        assert!(parse_blocks(&json!([{ "host": "x", "settings": {} }])).is_err());
+        assert!(parse_blocks(&json!([{ "hostname": "x" }])).is_err());
+        assert!(parse_blocks(&json!([{ "hostname": "[", "settings": {} }])).is_err());
+    }
+}
@@ -1,38 +1,104 @@
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
 
 pub struct Config {
     pub profiles_dir: PathBuf,
     pub backups_dir: PathBuf,
+    pub snapshots_dir: PathBuf,
+    pub journal_dir: PathBuf,
     pub settings_file: PathBuf,
+    pub prefs_file: PathBuf,
     pub current_profile_file: PathBuf,
+    pub aliases_file: PathBuf,
+    pub vars_file: PathBuf,
+    pub usage_file: PathBuf,
+    pub meta_dir: PathBuf,
+    pub history_dir: PathBuf,
+    pub trash_dir: PathBuf,
+    pub templates_dir: PathBuf,
 }
 
 impl Config {
     pub fn new() -> Result<Self> {
         let home = dirs::home_dir().context("Could not find home directory")?;
-        let claude_dir = home.join(".claude");
+        Ok(Self::from_dir(&home.join(".claude")))
+    }
+    
+    /// Layout of ccp's files under a Claude config directory (normally `~/.claude`).
+    pub fn from_dir(claude_dir: &Path) -> Self {
         let profiles_dir = claude_dir.join("profiles");
         let backups_dir = claude_dir.join("backups");
+        let snapshots_dir = backups_dir.join("snapshots");
+        let journal_dir = backups_dir.join("journal");
         let settings_file = claude_dir.join("settings.json");
+        let prefs_file = claude_dir.join("ccp.json");
         let current_profile_file = profiles_dir.join(".current");
-
-        Ok(Self {
+        let aliases_file = profiles_dir.join(".aliases.json");
+        let vars_file = profiles_dir.join(".vars.json");
+        let usage_file = profiles_dir.join(".last-used.json");
+        let meta_dir = profiles_dir.join(".meta");
+        let history_dir = profiles_dir.join(".history");
+        let trash_dir = profiles_dir.join(".trash");
+        let templates_dir = claude_dir.join("profile-templates");
+
+        Self {
             profiles_dir,
             backups_dir,
+            snapshots_dir,
+            journal_dir,
             settings_file,
+            prefs_file,
             current_profile_file,
-        })
+            aliases_file,
+            vars_file,
+            usage_file,
+            meta_dir,
+            history_dir,
+            trash_dir,
+            templates_dir,
+        }
     }
     
     pub fn profile_path(&self, name: &str) -> PathBuf {
-        self.profiles_dir.join(format!("{}.json", name))
+        self.profile_path_ext(name, "json")
+    }
+    
+    pub fn profile_path_ext(&self, name: &str, ext: &str) -> PathBuf {
+        self.profiles_dir.join(format!("{}.{}", name, ext))
+    }
+    
+    /// Executable whose output is the profile, for script-generated profiles.
+    pub fn script_path(&self, name: &str) -> PathBuf {
+        self.profiles_dir.join(format!("{}.profile.sh", name))
+    }
+    
+    pub fn meta_path(&self, name: &str) -> PathBuf {
+        self.meta_dir.join(format!("{}.json", name))
+    }
+    
+    /// An earlier revision of a profile. `@` cannot occur in profile names,
+    /// so `work@3` never collides with a profile in a `work/` namespace.
+    pub fn revision_path(&self, name: &str, number: u32) -> PathBuf {
+        self.history_dir.join(format!("{}@{}.json", name, number))
+    }
+    
+    /// Directory holding one deleted profile with its metadata and history.
+    pub fn trash_path(&self, id: &str) -> PathBuf {
+        self.trash_dir.join(id)
     }
     
     pub fn backup_path(&self, name: &str) -> PathBuf {
         self.backups_dir.join(format!("{}.json", name))
     }
     
+    pub fn backup_meta_path(&self, name: &str) -> PathBuf {
+        self.backups_dir.join(".meta").join(format!("{}.json", name))
+    }
+    
+    pub fn snapshot_path(&self, name: &str) -> PathBuf {
+        self.snapshots_dir.join(format!("{}.json", name))
+    }
+    
     pub fn ensure_dirs(&self) -> Result<()> {
         std::fs::create_dir_all(&self.profiles_dir)
             .context("Failed to create profiles directory")?;
@@ -42,23 +108,56 @@ impl Config {
     }
 }
 
+/// Check that a profile or backup name can be used as a file name inside its
+/// directory. `kind` is "Profile" or "Backup" and only used in messages.
+pub fn validate_name(kind: &str, name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("{} name cannot be empty", kind);
+    }
+    if name.len() > 100 {
+        bail!("{} name '{}' is too long (at most 100 characters)", kind, name);
+    }
+    if name.starts_with('.') {
+        bail!("{} name '{}' cannot start with '.'", kind, name);
+    }
+    if name.starts_with('-') {
+        bail!("{} name '{}' cannot start with '-'", kind, name);
+    }
+    if name.chars().any(char::is_whitespace) {
+        bail!("{} name '{}' cannot contain whitespace", kind, name);
+    }
+    if let Some(c) = name.chars().find(|c| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))) {
+        bail!(
+            "{} name '{}' cannot contain '{}'. Use letters, digits, '-', '_' and '.'",
+            kind, name, c
+        );
+    }
+    Ok(())
+}
+
+/// Like `validate_name`, but profiles may be grouped into namespaces such as
+/// `work/client-a`, stored as subdirectories of the profiles directory.
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("Profile name cannot be empty");
+    }
+    if name.starts_with('/') || name.ends_with('/') || name.contains("//") {
+        bail!("Profile name '{}' has an empty namespace part", name);
+    }
+    for segment in name.split('/') {
+        validate_name("Profile", segment)
+            .with_context(|| format!("Invalid profile name '{}'", name))?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
 
     fn create_test_config(temp_dir: &TempDir) -> Config {
-        let profiles_dir = temp_dir.path().join("profiles");
-        let backups_dir = temp_dir.path().join("backups");
-        let settings_file = temp_dir.path().join("settings.json");
-        let current_profile_file = profiles_dir.join(".current");
-
-        Config {
-            profiles_dir,
-            backups_dir,
-            settings_file,
-            current_profile_file,
-        }
+        Config::from_dir(temp_dir.path())
     }
 
     #[test]
@@ -79,6 +178,30 @@ mod tests {
         assert_eq!(path, temp_dir.path().join("backups/backup-20240101.json"));
     }
 
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("Profile", "work").is_ok());
+        assert!(validate_name("Profile", "anthropic-prod_v2.1").is_ok());
+        assert!(validate_name("Profile", "").is_err());
+        assert!(validate_name("Profile", "../evil").is_err());
+        assert!(validate_name("Profile", "a/b").is_err());
+        assert!(validate_name("Profile", ".current").is_err());
+        assert!(validate_name("Profile", "my profile").is_err());
+        assert!(validate_name("Profile", "-rf").is_err());
+        assert!(validate_name("Backup", &"x".repeat(101)).is_err());
+    }
+
+    #[test]
+    fn test_validate_profile_name() {
+        assert!(validate_profile_name("work/client-a").is_ok());
+        assert!(validate_profile_name("work/eu/client-a").is_ok());
+        assert!(validate_profile_name("work/").is_err());
+        assert!(validate_profile_name("/etc/passwd").is_err());
+        assert!(validate_profile_name("work//a").is_err());
+        assert!(validate_profile_name("work/../../evil").is_err());
+        assert!(validate_profile_name("work/.hidden").is_err());
+    }
+
     #[test]
     fn test_ensure_dirs_creates_directories() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+use crate::json_edit;
+
+/// File formats a profile can be written in. Whatever the format, profiles
+/// are handled as JSON values and applied to settings.json as JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Json, Format::Yaml, Format::Toml];
+
+    /// Recognised file extensions, preferred one first.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Json => &["json"],
+            Format::Yaml => &["yaml", "yml"],
+            Format::Toml => &["toml"],
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Format> {
+        Self::ALL.into_iter().find(|f| f.extensions().contains(&ext))
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(Self::from_extension)
+    }
+
+    pub fn parse(self, text: &str) -> Result<Value> {
+        match self {
+            Format::Json => json_edit::parse(text),
+            Format::Yaml => serde_yaml::from_str(text).context("Invalid YAML"),
+            Format::Toml => toml::from_str(text).context("Invalid TOML"),
+        }
+    }
+
+    pub fn to_string(self, data: &Value) -> Result<String> {
+        match self {
+            Format::Json => Ok(serde_json::to_string_pretty(data)?),
+            Format::Yaml => serde_yaml::to_string(data).context("Cannot represent profile as YAML"),
+            Format::Toml => toml::to_string_pretty(data).context("Cannot represent profile as TOML"),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(Format::from_extension("yml"), Some(Format::Yaml));
+        assert_eq!(Format::from_extension("toml"), Some(Format::Toml));
+        assert_eq!(Format::from_extension("txt"), None);
+    }
+
+    #[test]
+    fn test_yaml_roundtrip_keeps_order() {
+        let data = json!({ "model": "opus", "env": { "Z": "1", "A": "2" }, "permissions": { "allow": ["Bash"] } });
+        let text = Format::Yaml.to_string(&data).unwrap();
+        assert!(text.find("Z:").unwrap() < text.find("A:").unwrap());
+        assert_eq!(Format::Yaml.parse(&text).unwrap(), data);
+    }
+
+    #[test]
+    fn test_toml_roundtrip() {
+        let data = json!({ "model": "opus", "alwaysThinkingEnabled": true, "env": { "AWS_REGION": "us-east-1" } });
+        let text = Format::Toml.to_string(&data).unwrap();
+        assert_eq!(Format::Toml.parse(&text).unwrap(), data);
+    }
+
+    #[test]
+    fn test_toml_rejects_null() {
+        assert!(Format::Toml.to_string(&json!({ "model": null })).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use wait_timeout::ChildExt;
+
+/// How long a generator may run unless its spec says otherwise.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// A profile's `generator` field: a shell command, or a command with its
+/// own timeout. Its stdout must be a JSON object, which is merged into the
+/// profile when it is applied.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Generator {
+    Command(String),
+    Full {
+        command: String,
+        #[serde(default)]
+        timeout: Option<u64>,
+    },
+}
+
+impl Generator {
+    pub fn parse(value: &Value) -> Result<Self> {
+        serde_json::from_value(value.clone())
+            .context("'generator' must be a command string or { \"command\": ..., \"timeout\": seconds }")
+    }
+
+    /// Run the command with `sh -c` from `dir`, the profiles directory.
+    pub fn run(&self, dir: &Path) -> Result<Value> {
+        let (command, timeout) = match self {
+            Generator::Command(command) => (command, None),
+            Generator::Full { command, timeout } => (command, *timeout),
+        };
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command).current_dir(dir);
+        // Its own process group, so a timeout also stops what it started
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        run(cmd, command, timeout.unwrap_or(DEFAULT_TIMEOUT_SECS))
+    }
+}
+
+#[cfg(unix)]
+fn kill_group(child: &mut Child) {
+    // The child leads its group, so the group id is its pid
+    match libc::pid_t::try_from(child.id()) {
+        // SAFETY: killpg only sends a signal; an invalid group is reported as an error
+        Ok(pgid) if unsafe { libc::killpg(pgid, libc::SIGKILL) } == 0 => {}
+        _ => {
+            child.kill().ok();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child) {
+    child.kill().ok();
+}
+
+fn run(mut cmd: Command, label: &str, timeout_secs: u64) -> Result<Value> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start generator '{}'", label))?;
+
+    // Drain the pipes while waiting so a chatty generator can't block on a full pipe
+    let mut stdout = child.stdout.take().context("Generator stdout unavailable")?;
+    let mut stderr = child.stderr.take().context("Generator stderr unavailable")?;
+    let stdout_reader = thread::spawn(move || {
+        let mut out = String::new();
+        stdout.read_to_string(&mut out).map(|_| out)
+    });
+    let stderr_reader = thread::spawn(move || {
+        let mut err = String::new();
+        stderr.read_to_string(&mut err).map(|_| err)
+    });
+
+    let status = match child.wait_timeout(Duration::from_secs(timeout_secs))? {
+        Some(status) => status,
+        None => {
+            kill_group(&mut child);
+            child.wait().ok();
+            bail!("Generator '{}' timed out after {}s", label, timeout_secs);
+        }
+    };
+
+    let stdout = stdout_reader.join().map_err(|_| anyhow::anyhow!("Generator output reader panicked"))??;
+    let stderr = stderr_reader.join().map_err(|_| anyhow::anyhow!("Generator output reader panicked"))??;
+
+    if !status.success() {
+        let detail = stderr.trim();
+        if detail.is_empty() {
+            bail!("Generator '{}' failed ({})", label, status);
+        }
+        bail!("Generator '{}' failed ({}): {}", label, status, detail);
+    }
+
+    let value: Value = serde_json::from_str(&stdout)
+        .with_context(|| format!("Generator '{}' did not print valid JSON", label))?;
+    if !value.is_object() {
+        bail!("Generator '{}' must print a JSON object", label);
+    }
+    Ok(value)
+}
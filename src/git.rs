@@ -0,0 +1,93 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Written by `ccp init --git`. Machine-local state and anything that may hold
+/// secrets stay out of the repository; profiles reference secrets through
+/// `${VAR}` instead.
+pub const GITIGNORE: &str = "\
+# Machine-local state
+.current
+.last-used.json
+.history/
+.trash/
+
+# Secrets are kept out of the repository: put them in .vars.json or the
+# environment and reference them from profiles as ${VAR}
+.vars.json
+*.secret
+*.secret.*
+.env
+";
+
+/// Committer used when git has no identity configured, so that `init --git`
+/// works on a fresh machine.
+const FALLBACK_IDENTITY: [&str; 4] = ["-c", "user.name=ccp", "-c", "user.email=ccp@localhost"];
+
+/// Whether `dir` is the top level of a git work tree. A profiles directory
+/// nested inside some other repository doesn't count.
+pub fn is_repo(dir: &Path) -> bool {
+    let Ok(output) = git(dir, &["rev-parse", "--show-toplevel"]) else {
+        return false;
+    };
+    let toplevel = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim_end_matches('\n'));
+    match (toplevel.canonicalize(), dir.canonicalize()) {
+        (Ok(toplevel), Ok(dir)) => toplevel == dir,
+        _ => false,
+    }
+}
+
+fn has_identity(dir: &Path) -> bool {
+    git(dir, &["var", "GIT_AUTHOR_IDENT"]).is_ok() && git(dir, &["var", "GIT_COMMITTER_IDENT"]).is_ok()
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Output> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git. Is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            // The subcommand, past any `-c name=value` options
+            args.iter().find(|arg| !arg.starts_with('-') && !arg.contains('=')).copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    Ok(output)
+}
+
+/// Make `dir` a repository with ccp's .gitignore, unless it already is one.
+/// Returns whether a new repository was created.
+pub fn init(dir: &Path) -> Result<bool> {
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(&gitignore, GITIGNORE)
+            .with_context(|| format!("Failed to write {}", gitignore.display()))?;
+    }
+    if is_repo(dir) {
+        return Ok(false);
+    }
+    git(dir, &["init", "--quiet"])?;
+    Ok(true)
+}
+
+/// Stage everything and commit it. Returns false when there was nothing to
+/// commit.
+pub fn commit_all(dir: &Path, message: &str) -> Result<bool> {
+    git(dir, &["add", "--all"])?;
+    let status = git(dir, &["status", "--porcelain"])?;
+    if status.stdout.is_empty() {
+        return Ok(false);
+    }
+    let mut args = Vec::new();
+    if !has_identity(dir) {
+        args.extend(FALLBACK_IDENTITY);
+    }
+    args.extend(["commit", "--quiet", "-m", message]);
+    git(dir, &args)?;
+    Ok(true)
+}
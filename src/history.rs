@@ -0,0 +1,77 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::conditions;
+use crate::format::Format;
+
+/// A profile's content from before a change, kept under
+/// `profiles/.history/<name>@<number>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    /// When the content was replaced
+    pub time: DateTime<Local>,
+    /// What replaced it, e.g. "Set model in work"
+    pub command: String,
+    /// Which keys the change touched
+    pub summary: String,
+    pub format: Format,
+    /// The file exactly as it was
+    pub content: String,
+}
+
+static COMMAND: OnceLock<String> = OnceLock::new();
+
+/// Describe the running command for the revision log, e.g. "Set model in
+/// work". Never the arguments as typed, since values may be secrets.
+pub fn set_command(description: String) {
+    COMMAND.set(description).ok();
+}
+
+/// The running command, as described by `set_command`.
+pub fn current_command() -> String {
+    COMMAND.get().cloned().unwrap_or_else(|| "ccp".to_string())
+}
+
+/// The key paths that differ between `old` and `new`, e.g.
+/// `model, env.API_URL and 2 more`.
+pub fn summarize(old: &Value, new: &Value) -> String {
+    let old: BTreeMap<String, Value> = conditions::leaves(old, "").into_iter().collect();
+    let new: BTreeMap<String, Value> = conditions::leaves(new, "").into_iter().collect();
+
+    let mut changed: Vec<&str> = old.keys()
+        .filter(|key| new.get(*key) != old.get(*key))
+        .chain(new.keys().filter(|key| !old.contains_key(*key)))
+        .filter(|key| !key.is_empty())
+        .map(String::as_str)
+        .collect();
+    changed.sort();
+
+    const SHOWN: usize = 3;
+    match changed.len() {
+        0 => "no key changes".to_string(),
+        n if n <= SHOWN => changed.join(", "),
+        n => format!("{} and {} more", changed[..SHOWN].join(", "), n - SHOWN),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_summarize() {
+        let old = json!({ "model": "sonnet", "env": { "A": "1", "B": "2" } });
+        assert_eq!(summarize(&old, &old), "no key changes");
+        assert_eq!(summarize(&old, &json!({ "model": "opus", "env": { "A": "1", "B": "2" } })), "model");
+        assert_eq!(summarize(&old, &json!({ "env": { "A": "1", "C": "3" } })), "env.B, env.C, model");
+        assert_eq!(
+            summarize(&json!({}), &json!({ "a": 1, "b": 2, "c": 3, "d": 4, "e": 5 })),
+            "a, b, c and 2 more",
+        );
+    }
+}
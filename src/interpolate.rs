@@ -0,0 +1,148 @@
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+/// Key paths holding shell commands. Claude Code runs them through a shell
+/// that expands `${VAR}` itself (e.g. `${CLAUDE_PROJECT_DIR}`), so they are
+/// left as written.
+const SHELL_COMMANDS: &[&str] = &["hooks", "statusLine.command"];
+
+/// Expand `${VAR}` and `${VAR:-default}` in every string value of `data`,
+/// except under `SHELL_COMMANDS`. `$${` stands for a literal `${`. `lookup`
+/// supplies variable values; an undefined variable without a default is an
+/// error naming the key path.
+pub fn expand<F>(data: &Value, lookup: &F) -> Result<Value>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    expand_at(data, "", lookup)
+}
+
+fn expand_at<F>(value: &Value, path: &str, lookup: &F) -> Result<Value>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if SHELL_COMMANDS.contains(&path) {
+        return Ok(value.clone());
+    }
+    Ok(match value {
+        Value::String(s) => match expand_str(s, lookup) {
+            Ok(expanded) => Value::String(expanded),
+            Err(var) => bail!("Undefined variable '{}' in '{}'", var, path),
+        },
+        Value::Object(map) => {
+            let mut out = serde_json::Map::new();
+            for (key, v) in map {
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                out.insert(key.clone(), expand_at(v, &child, lookup)?);
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => {
+            let mut out = Vec::with_capacity(items.len());
+            for (i, v) in items.iter().enumerate() {
+                out.push(expand_at(v, &format!("{}[{}]", path, i), lookup)?);
+            }
+            Value::Array(out)
+        }
+        other => other.clone(),
+    })
+}
+
+/// Expand placeholders in one string. On failure, returns the name of the
+/// undefined variable.
+fn expand_str<F>(s: &str, lookup: &F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        if let Some(after) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+            continue;
+        }
+
+        let placeholder = tail.strip_prefix("${")
+            .and_then(|inner| inner.find('}').map(|end| &inner[..end]))
+            .filter(|inner| is_var_name(inner.split_once(":-").map_or(*inner, |(name, _)| name)));
+        let Some(inner) = placeholder else {
+            out.push('$');
+            rest = &tail[1..];
+            continue;
+        };
+
+        let (name, default) = match inner.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (inner, None),
+        };
+        // Like the shell, `:-` also replaces an empty value
+        let value = lookup(name).filter(|v| default.is_none() || !v.is_empty());
+        match value.or_else(|| default.map(str::to_string)) {
+            Some(value) => out.push_str(&value),
+            None => return Err(name.to_string()),
+        }
+        rest = &tail[inner.len() + 3..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "ORG" => Some("acme".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_str() {
+        assert_eq!(expand_str("https://${ORG}.example/v1", &lookup).unwrap(), "https://acme.example/v1");
+        assert_eq!(expand_str("${HOST:-localhost}:${ORG}", &lookup).unwrap(), "localhost:acme");
+        assert_eq!(expand_str("${EMPTY:-x}", &lookup).unwrap(), "x");
+        assert_eq!(expand_str("${EMPTY}", &lookup).unwrap(), "");
+        assert_eq!(expand_str("cost $5, literal $${ORG}", &lookup).unwrap(), "cost $5, literal ${ORG}");
+        assert_eq!(expand_str("${not a var}", &lookup).unwrap(), "${not a var}");
+        assert_eq!(expand_str("${MISSING}", &lookup).unwrap_err(), "MISSING");
+    }
+
+    #[test]
+    fn test_expand_names_key_path() {
+        let data = json!({ "env": { "URL": "${ORG}" }, "permissions": { "allow": ["Bash(${NOPE})"] } });
+        let err = expand(&data, &lookup).unwrap_err().to_string();
+        assert_eq!(err, "Undefined variable 'NOPE' in 'permissions.allow[0]'");
+
+        let data = json!({ "env": { "URL": "${ORG}" }, "alwaysThinkingEnabled": true });
+        assert_eq!(expand(&data, &lookup).unwrap(), json!({ "env": { "URL": "acme" }, "alwaysThinkingEnabled": true }));
+    }
+
+    #[test]
+    fn test_expand_leaves_shell_commands_alone() {
+        let data = json!({
+            "hooks": { "PostToolUse": [{ "hooks": [{ "command": "${CLAUDE_PROJECT_DIR}/lint.sh" }] }] },
+            "statusLine": { "type": "command", "command": "echo ${MISSING} $${ORG}" },
+            "env": { "URL": "${ORG}" },
+        });
+        let expanded = expand(&data, &lookup).unwrap();
+        assert_eq!(expanded["hooks"], data["hooks"]);
+        assert_eq!(expanded["statusLine"], data["statusLine"]);
+        assert_eq!(expanded["env"]["URL"], "acme");
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::format::Format;
+use crate::profile::remove_empty_dirs;
+use crate::snapshot::{self, Snapshot};
+
+/// One file a command changed, with its content before and after. `None`
+/// means the file did not exist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// `settings.json`, `ccp.json` or `profiles/<path>`
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    /// A script profile, to be written back executable
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub executable: bool,
+}
+
+/// One mutating command and the files it changed. Undo puts back only those
+/// files, so changes made to anything else in the meantime are kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    /// What it did, e.g. "Delete profile work"
+    pub summary: String,
+    pub changes: Vec<Change>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undone: bool,
+}
+
+impl Entry {
+    pub fn is_undone(&self) -> bool {
+        self.undone
+    }
+}
+
+impl Change {
+    /// The profile whose file or metadata this changes, if any.
+    pub fn profile(&self) -> Option<String> {
+        let rel = self.path.strip_prefix(PROFILES)?;
+        if let Some(meta) = rel.strip_prefix(".meta/") {
+            return meta.strip_suffix(".json").map(str::to_string);
+        }
+        if rel.split('/').any(|part| part.starts_with('.')) {
+            return None;
+        }
+        if let Some(name) = rel.strip_suffix(".profile.sh") {
+            return Some(name.to_string());
+        }
+        Format::from_path(Path::new(rel))?;
+        rel.rsplit_once('.').map(|(stem, _)| stem.to_string())
+    }
+}
+
+const SETTINGS: &str = "settings.json";
+const PREFS: &str = "ccp.json";
+const PROFILES: &str = "profiles/";
+
+/// The files that differ between two snapshots.
+pub fn changes(before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let before_files = texts(before);
+    let after_files = texts(after);
+    let paths: BTreeSet<&String> = before_files.keys().chain(after_files.keys()).collect();
+
+    paths.into_iter()
+        .filter(|path| before_files.get(*path) != after_files.get(*path))
+        .map(|path| {
+            let rel = path.strip_prefix(PROFILES).unwrap_or_default();
+            Change {
+                path: path.clone(),
+                before: before_files.get(path).map(|c| c.to_string()),
+                after: after_files.get(path).map(|c| c.to_string()),
+                executable: before.executable.iter().chain(&after.executable).any(|e| e == rel),
+            }
+        })
+        .collect()
+}
+
+fn texts(snapshot: &Snapshot) -> BTreeMap<String, &str> {
+    let mut texts: BTreeMap<String, &str> = snapshot.files.iter()
+        .map(|(rel, content)| (format!("{}{}", PROFILES, rel), content.as_str()))
+        .collect();
+    if let Some(settings) = &snapshot.settings {
+        texts.insert(SETTINGS.to_string(), settings);
+    }
+    if let Some(prefs) = &snapshot.prefs {
+        texts.insert(PREFS.to_string(), prefs);
+    }
+    texts
+}
+
+fn resolve(config: &Config, path: &str) -> Result<PathBuf> {
+    match path {
+        SETTINGS => Ok(config.settings_file.clone()),
+        PREFS => Ok(config.prefs_file.clone()),
+        _ => match path.strip_prefix(PROFILES) {
+            Some(rel) if !rel.split('/').any(|part| part.is_empty() || part == "..") => {
+                Ok(config.profiles_dir.join(rel))
+            }
+            _ => bail!("Journal entry contains an unsafe path '{}'", path),
+        },
+    }
+}
+
+/// Take the files of `entries`, in order, from their `from` to their `to`
+/// content. Files that no longer hold what the journal expects were changed
+/// by something else since; they are only overwritten with `force`.
+fn apply(config: &Config, entries: &[(u64, Entry)], undo: bool, force: bool) -> Result<()> {
+    let mut state: BTreeMap<&str, Option<String>> = BTreeMap::new();
+    let mut executable = BTreeSet::new();
+    let mut conflicts = BTreeSet::new();
+    for (_, entry) in entries {
+        for change in &entry.changes {
+            let (from, to) = if undo {
+                (&change.after, &change.before)
+            } else {
+                (&change.before, &change.after)
+            };
+            let current = match state.get(change.path.as_str()) {
+                Some(content) => content.clone(),
+                None => read_optional(&resolve(config, &change.path)?)?,
+            };
+            if &current != from {
+                conflicts.insert(change.path.as_str());
+            }
+            state.insert(&change.path, to.clone());
+            if change.executable {
+                executable.insert(change.path.as_str());
+            }
+        }
+    }
+    if !conflicts.is_empty() && !force {
+        bail!(
+            "Changed since then: {}. Pass --force to overwrite anyway.",
+            conflicts.into_iter().collect::<Vec<_>>().join(", "),
+        );
+    }
+
+    for (path, content) in state {
+        let target = resolve(config, path)?;
+        match content {
+            Some(content) => {
+                if let Some(dir) = target.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&target, content)
+                    .with_context(|| format!("Failed to write {}", target.display()))?;
+                if executable.contains(path) {
+                    snapshot::set_executable(&target)?;
+                }
+            }
+            None if target.exists() => {
+                fs::remove_file(&target)
+                    .with_context(|| format!("Failed to remove {}", target.display()))?;
+                remove_empty_dirs(&target, &config.profiles_dir);
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// The journal, one numbered file per entry under `backups/journal/`.
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new(config: &Config) -> Self {
+        Self { dir: config.journal_dir.clone() }
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", seq))
+    }
+
+    /// All entries, oldest first.
+    pub fn entries(&self) -> Result<Vec<(u64, Entry)>> {
+        let mut entries = Vec::new();
+        if !self.dir.is_dir() {
+            return Ok(entries);
+        }
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            let Some(seq) = path.file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+                .filter(|_| path.extension().is_some_and(|e| e == "json"))
+            else {
+                continue;
+            };
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let entry = serde_json::from_str(&content)
+                .with_context(|| format!("Invalid journal entry {}", path.display()))?;
+            entries.push((seq, entry));
+        }
+        entries.sort_by_key(|(seq, _)| *seq);
+        Ok(entries)
+    }
+
+    fn save(&self, seq: u64, entry: &Entry) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.path(seq);
+        fs::write(&path, serde_json::to_string(entry)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Add an entry for a command that changed something. Undone entries
+    /// can no longer be redone after a new change, and the oldest entries
+    /// beyond `limit` are dropped.
+    pub fn record(&self, entry: &Entry, limit: usize) -> Result<()> {
+        let entries = self.entries()?;
+        for (seq, old) in &entries {
+            if old.is_undone() {
+                fs::remove_file(self.path(*seq)).ok();
+            }
+        }
+        let seq = entries.last().map_or(1, |(seq, _)| seq + 1);
+        self.save(seq, entry)?;
+
+        let kept: Vec<u64> = entries.iter()
+            .filter(|(_, e)| !e.is_undone())
+            .map(|(seq, _)| *seq)
+            .chain([seq])
+            .collect();
+        let excess = kept.len().saturating_sub(limit);
+        for seq in &kept[..excess] {
+            fs::remove_file(self.path(*seq)).ok();
+        }
+        Ok(())
+    }
+
+    /// The latest `steps` commands that have not been undone, newest first.
+    pub fn undoable(&self, steps: usize) -> Result<Vec<(u64, Entry)>> {
+        let done: Vec<(u64, Entry)> = self.entries()?
+            .into_iter()
+            .filter(|(_, e)| !e.is_undone())
+            .collect();
+        if done.is_empty() {
+            bail!("Nothing to undo");
+        }
+        if steps > done.len() {
+            bail!("Only {} change(s) can be undone", done.len());
+        }
+
+        Ok(done.into_iter().rev().take(steps).collect())
+    }
+
+    /// The earliest `steps` undone commands.
+    pub fn redoable(&self, steps: usize) -> Result<Vec<(u64, Entry)>> {
+        let undone: Vec<(u64, Entry)> = self.entries()?
+            .into_iter()
+            .filter(|(_, e)| e.is_undone())
+            .collect();
+        if undone.is_empty() {
+            bail!("Nothing to redo");
+        }
+        if steps > undone.len() {
+            bail!("Only {} change(s) can be redone", undone.len());
+        }
+
+        Ok(undone.into_iter().take(steps).collect())
+    }
+
+    /// Put back the files changed by `picked`, from `undoable`. Returns the
+    /// entries undone.
+    pub fn undo(&self, config: &Config, picked: Vec<(u64, Entry)>, force: bool) -> Result<Vec<Entry>> {
+        apply(config, &picked, true, force)?;
+        self.mark(picked, true)
+    }
+
+    /// Re-apply `picked`, from `redoable`. Returns the entries redone.
+    pub fn redo(&self, config: &Config, picked: Vec<(u64, Entry)>, force: bool) -> Result<Vec<Entry>> {
+        apply(config, &picked, false, force)?;
+        self.mark(picked, false)
+    }
+
+    fn mark(&self, entries: Vec<(u64, Entry)>, undone: bool) -> Result<Vec<Entry>> {
+        let mut marked = Vec::new();
+        for (seq, mut entry) in entries {
+            entry.undone = undone;
+            self.save(seq, &entry)?;
+            marked.push(entry);
+        }
+        Ok(marked)
+    }
+}
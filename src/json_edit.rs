@@ -0,0 +1,582 @@
+//! Minimal-diff rewriting of JSON files.
+//!
+//! Instead of re-serializing a whole document, `update` re-parses the original
+//! text with byte spans and splices in only the values that changed. Keys keep
+//! their order, new content follows the file's indentation, and everything
+//! that was not touched stays byte-identical.
+//!
+//! Profiles may be JSONC: `//` and `/* */` comments and trailing commas are
+//! accepted on read and carried through untouched on write.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Parse JSON that may contain comments and trailing commas.
+pub fn parse(text: &str) -> Result<Value> {
+    Ok(serde_json::from_str(&strip_comments(text))?)
+}
+
+/// Blank out comments and trailing commas so strict JSON parsers accept the
+/// text. Byte offsets and line breaks are kept, so parse errors still point
+/// at the right line and column.
+pub fn strip_comments(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut pending_comma = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+                pending_comma = None;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    out[i] = b' ';
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = text[i + 2..].find("*/").map_or(bytes.len(), |e| i + 2 + e + 2);
+                for byte in &mut out[i..end] {
+                    if *byte != b'\n' {
+                        *byte = b' ';
+                    }
+                }
+                i = end;
+            }
+            b',' => {
+                pending_comma = Some(i);
+                i += 1;
+            }
+            b'}' | b']' => {
+                if let Some(comma) = pending_comma.take() {
+                    out[comma] = b' ';
+                }
+                i += 1;
+            }
+            b if b.is_ascii_whitespace() => i += 1,
+            _ => {
+                pending_comma = None;
+                i += 1;
+            }
+        }
+    }
+
+    // Only ASCII bytes were replaced, and always with spaces
+    String::from_utf8(out).unwrap_or_else(|_| text.to_string())
+}
+
+/// Rewrite `original` so that it parses to `new`, changing as little text as possible.
+pub fn update(original: &str, new: &Value) -> Result<String> {
+    let old = parse(original).context("Failed to parse original JSON")?;
+
+    let mut parser = Parser { text: original, pos: 0 };
+    let root = parser.value()?;
+
+    let rewriter = Rewriter {
+        text: original,
+        unit: detect_indent(original),
+    };
+    let body = rewriter.rewrite(&root, &old, new, false)?;
+
+    Ok(format!("{}{}{}", &original[..root.start], body, &original[root.end..]))
+}
+
+/// Guess the indentation unit of a document from its first indented line,
+/// defaulting to two spaces.
+pub fn detect_indent(text: &str) -> String {
+    for line in text.lines() {
+        let indent: String = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        if !indent.is_empty() && indent.len() < line.len() {
+            return if indent.starts_with('\t') { "\t".to_string() } else { indent };
+        }
+    }
+    "  ".to_string()
+}
+
+struct Node {
+    start: usize,
+    end: usize,
+    kind: Kind,
+}
+
+enum Kind {
+    Object(Vec<Member>),
+    Array(Vec<Node>),
+    Scalar,
+}
+
+struct Member {
+    key: String,
+    start: usize,
+    value: Node,
+    comma: Option<usize>,
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    /// Skip whitespace and comments. An unterminated block comment is left
+    /// in place so callers can tell the line does not end cleanly.
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = &self.text[self.pos..];
+            if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(body) = rest.strip_prefix("/*") {
+                match body.find("*/") {
+                    Some(end) => self.pos += end + 4,
+                    None => return,
+                }
+            } else if self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_trivia();
+        if self.peek() != Some(byte) {
+            bail!("Expected '{}' at byte {}", byte as char, self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Node> {
+        self.skip_trivia();
+        let start = self.pos;
+
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => {
+                self.string()?;
+                Ok(Node { start, end: self.pos, kind: Kind::Scalar })
+            }
+            Some(_) => {
+                while self.peek().is_some_and(|b| !b.is_ascii_whitespace() && !b",]}/".contains(&b)) {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    bail!("Unexpected character at byte {}", start);
+                }
+                Ok(Node { start, end: self.pos, kind: Kind::Scalar })
+            }
+            None => bail!("Unexpected end of JSON"),
+        }
+    }
+
+    fn string(&mut self) -> Result<()> {
+        self.expect(b'"')?;
+        loop {
+            match self.peek() {
+                Some(b'\\') => self.pos += 2,
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some(_) => self.pos += 1,
+                None => bail!("Unterminated string"),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Node> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut members = Vec::new();
+
+        loop {
+            self.skip_trivia();
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                break;
+            }
+
+            let key_start = self.pos;
+            self.string()?;
+            let key: String = serde_json::from_str(&self.text[key_start..self.pos])?;
+            self.expect(b':')?;
+            let value = self.value()?;
+
+            self.skip_trivia();
+            let comma = if self.peek() == Some(b',') {
+                self.pos += 1;
+                Some(self.pos - 1)
+            } else {
+                None
+            };
+
+            members.push(Member { key, start: key_start, value, comma });
+
+            if comma.is_none() {
+                self.expect(b'}')?;
+                break;
+            }
+        }
+
+        Ok(Node { start, end: self.pos, kind: Kind::Object(members) })
+    }
+
+    fn array(&mut self) -> Result<Node> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_trivia();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                break;
+            }
+
+            items.push(self.value()?);
+
+            self.skip_trivia();
+            if self.peek() == Some(b',') {
+                self.pos += 1;
+            } else {
+                self.expect(b']')?;
+                break;
+            }
+        }
+
+        Ok(Node { start, end: self.pos, kind: Kind::Array(items) })
+    }
+}
+
+/// One member of a rewritten object: everything before its value (leading
+/// whitespace and key), the value itself, and what follows the comma.
+struct Entry {
+    prefix: String,
+    value: String,
+    between: String,
+    comma: bool,
+    after: String,
+}
+
+struct Rewriter<'a> {
+    text: &'a str,
+    unit: String,
+}
+
+impl Rewriter<'_> {
+    fn rewrite(&self, node: &Node, old: &Value, new: &Value, inline: bool) -> Result<String> {
+        if old == new {
+            return Ok(self.text[node.start..node.end].to_string());
+        }
+
+        match (&node.kind, old, new) {
+            (Kind::Object(members), Value::Object(old), Value::Object(new)) if !members.is_empty() => {
+                self.rewrite_object(node, members, old, new, inline)
+            }
+            (Kind::Array(items), Value::Array(old), Value::Array(new)) if old.len() == new.len() => {
+                let mut out = String::new();
+                let mut pos = node.start;
+                for ((item, old), new) in items.iter().zip(old).zip(new) {
+                    out.push_str(&self.text[pos..item.start]);
+                    out.push_str(&self.rewrite(item, old, new, inline)?);
+                    pos = item.end;
+                }
+                out.push_str(&self.text[pos..node.end]);
+                Ok(out)
+            }
+            _ => self.render(new, &self.line_indent(node.start), inline),
+        }
+    }
+
+    fn rewrite_object(
+        &self,
+        node: &Node,
+        members: &[Member],
+        old: &Map<String, Value>,
+        new: &Map<String, Value>,
+        inline: bool,
+    ) -> Result<String> {
+        let text = self.text;
+        let multiline = !inline && text[node.start..node.end].contains('\n');
+        let trailing_comma = members.last().is_some_and(|m| m.comma.is_some());
+
+        // Split the object into head, one chunk per member, and tail. In
+        // multi-line objects a chunk runs to the end of its line, so removing
+        // a member removes its whole line.
+        let head_end = if multiline { self.line_end_after(node.start + 1) } else { node.start + 1 };
+        let mut chunk_start = head_end;
+        let mut entries = Vec::new();
+
+        for member in members {
+            let after_value = member.comma.map_or(member.value.end, |c| c + 1);
+            let chunk_end = if multiline { self.line_end_after(after_value) } else { after_value };
+
+            if let Some(new_value) = new.get(&member.key) {
+                let old_value = old.get(&member.key).unwrap_or(&Value::Null);
+                let (between, after) = match member.comma {
+                    Some(c) => (&text[member.value.end..c], &text[c + 1..chunk_end]),
+                    None => (&text[member.value.end..chunk_end], ""),
+                };
+                entries.push(Entry {
+                    prefix: text[chunk_start..member.value.start].to_string(),
+                    value: self.rewrite(&member.value, old_value, new_value, !multiline)?,
+                    between: between.to_string(),
+                    comma: member.comma.is_some(),
+                    after: after.to_string(),
+                });
+            }
+
+            chunk_start = chunk_end;
+        }
+        let tail = &text[chunk_start..node.end];
+
+        if !multiline {
+            // Keep the original spacing after '{' if the first member went away
+            if let (Some(first), Some(entry)) = (members.first(), entries.first_mut()) {
+                let lead = &text[head_end..first.start];
+                entry.prefix = format!("{}{}", lead, entry.prefix.trim_start());
+            }
+        }
+
+        let indent = self.line_indent(members[0].start);
+        for (key, value) in new.iter().filter(|(k, _)| !old.contains_key(*k)) {
+            let key = serde_json::to_string(key)?;
+            let entry = if multiline {
+                Entry {
+                    prefix: format!("{}{}: ", indent, key),
+                    value: self.render(value, &indent, false)?,
+                    between: String::new(),
+                    comma: false,
+                    after: "\n".to_string(),
+                }
+            } else {
+                let lead = if entries.is_empty() { "" } else { " " };
+                Entry {
+                    prefix: format!("{}{}: ", lead, key),
+                    value: self.render(value, "", true)?,
+                    between: String::new(),
+                    comma: false,
+                    after: String::new(),
+                }
+            };
+
+            // A kept last member may end on the closing brace's line
+            if multiline {
+                if let Some(last) = entries.last_mut() {
+                    let end = if last.comma { &last.after } else { &last.between };
+                    if !end.ends_with('\n') {
+                        last.after.push('\n');
+                    }
+                }
+            }
+            entries.push(entry);
+        }
+
+        if entries.is_empty() {
+            return Ok("{}".to_string());
+        }
+
+        let mut out = text[node.start..head_end].to_string();
+        let count = entries.len();
+        for (i, entry) in entries.into_iter().enumerate() {
+            let wants_comma = i + 1 < count || trailing_comma;
+            out.push_str(&entry.prefix);
+            out.push_str(&entry.value);
+            if wants_comma && !entry.comma {
+                // Put the comma right after the value, ahead of any line break
+                let trimmed = entry.between.trim_end_matches(['\n', '\r', ' ', '\t']);
+                out.push_str(trimmed);
+                out.push(',');
+                out.push_str(&entry.between[trimmed.len()..]);
+            } else {
+                out.push_str(&entry.between);
+                if wants_comma {
+                    out.push(',');
+                }
+            }
+            out.push_str(&entry.after);
+        }
+        out.push_str(tail);
+
+        Ok(out)
+    }
+
+    /// Position just past the end of the line starting at `pos`, if the rest
+    /// of that line holds nothing but whitespace and comments; otherwise `pos`.
+    fn line_end_after(&self, pos: usize) -> usize {
+        let rest = &self.text[pos..];
+        match rest.find('\n') {
+            Some(i) => {
+                let mut line = Parser { text: &rest[..i], pos: 0 };
+                line.skip_trivia();
+                if line.pos == i { pos + i + 1 } else { pos }
+            }
+            None => pos,
+        }
+    }
+
+    /// Leading whitespace of the line containing `pos`.
+    fn line_indent(&self, pos: usize) -> String {
+        let line_start = self.text[..pos].rfind('\n').map_or(0, |i| i + 1);
+        self.text[line_start..pos]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
+    fn render(&self, value: &Value, indent: &str, inline: bool) -> Result<String> {
+        if inline {
+            return Ok(render_inline(value));
+        }
+
+        let mut buf = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(self.unit.as_bytes());
+        let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+        value.serialize(&mut ser)?;
+
+        let pretty = String::from_utf8(buf)?;
+        Ok(pretty.replace('\n', &format!("\n{}", indent)))
+    }
+}
+
+/// Single-line rendering with a space after ':' and ',' to match hand-written JSON.
+fn render_inline(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let members: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", Value::String(k.clone()), render_inline(v)))
+                .collect();
+            format!("{{{}}}", members.join(", "))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(render_inline).collect();
+            format!("[{}]", items.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn apply(original: &str, edit: impl FnOnce(&mut Value)) -> String {
+        let mut data = parse(original).unwrap();
+        edit(&mut data);
+        let updated = update(original, &data).unwrap();
+        assert_eq!(parse(&updated).unwrap(), data);
+        updated
+    }
+
+    #[test]
+    fn test_update_unchanged_is_identical() {
+        let original = "{\n    \"z\": 1,\n    \"a\": [1,2]\n}\n";
+        assert_eq!(update(original, &serde_json::from_str(original).unwrap()).unwrap(), original);
+    }
+
+    #[test]
+    fn test_update_changes_only_value() {
+        let original = "{\n    \"model\": \"sonnet\",\n    \"env\": {\"A\": \"1\"},\n    \"alpha\": true\n}\n";
+        let updated = apply(original, |d| d["model"] = json!("opus"));
+        assert_eq!(updated, original.replace("sonnet", "opus"));
+    }
+
+    #[test]
+    fn test_update_adds_key_with_file_indent() {
+        let original = "{\n\t\"model\": \"sonnet\",\n\t\"env\": {\n\t\t\"A\": \"1\"\n\t}\n}";
+        let updated = apply(original, |d| d["env"]["B"] = json!("2"));
+        assert_eq!(updated, "{\n\t\"model\": \"sonnet\",\n\t\"env\": {\n\t\t\"A\": \"1\",\n\t\t\"B\": \"2\"\n\t}\n}");
+    }
+
+    #[test]
+    fn test_update_adds_nested_object() {
+        let original = "{\n  \"model\": \"sonnet\"\n}";
+        let updated = apply(original, |d| d["env"] = json!({ "A": "1" }));
+        assert_eq!(updated, "{\n  \"model\": \"sonnet\",\n  \"env\": {\n    \"A\": \"1\"\n  }\n}");
+    }
+
+    #[test]
+    fn test_update_removes_last_key() {
+        let original = "{\n  \"zeta\": 1,\n  \"alpha\": 2,\n  \"mid\": 3\n}";
+        let updated = apply(original, |d| {
+            d.as_object_mut().unwrap().shift_remove("mid");
+        });
+        assert_eq!(updated, "{\n  \"zeta\": 1,\n  \"alpha\": 2\n}");
+    }
+
+    #[test]
+    fn test_update_removes_middle_key() {
+        let original = "{\n  \"zeta\": 1,\n  \"alpha\": 2,\n  \"mid\": 3\n}";
+        let updated = apply(original, |d| {
+            d.as_object_mut().unwrap().shift_remove("alpha");
+        });
+        assert_eq!(updated, "{\n  \"zeta\": 1,\n  \"mid\": 3\n}");
+    }
+
+    #[test]
+    fn test_update_inline_object() {
+        let original = r#"{"model": "sonnet-4", "custom": "value"}"#;
+        let updated = apply(original, |d| {
+            d.as_object_mut().unwrap().shift_remove("model");
+        });
+        assert_eq!(updated, r#"{"custom": "value"}"#);
+
+        let updated = apply(original, |d| d["env"] = json!({ "A": "1" }));
+        assert_eq!(updated, r#"{"model": "sonnet-4", "custom": "value", "env": {"A": "1"}}"#);
+    }
+
+    #[test]
+    fn test_update_remove_all_keys() {
+        let original = "{\n  \"a\": 1\n}";
+        assert_eq!(apply(original, |d| *d = json!({})), "{}");
+    }
+
+    #[test]
+    fn test_parse_jsonc() {
+        let text = "{\n  // model for daily work\n  \"model\": \"opus\", /* inline */\n  \"url\": \"http://x//y\",\n  \"list\": [1, 2,],\n}";
+        assert_eq!(parse(text).unwrap(), json!({ "model": "opus", "url": "http://x//y", "list": [1, 2] }));
+        assert_eq!(strip_comments(text).len(), text.len());
+    }
+
+    #[test]
+    fn test_update_keeps_comments() {
+        let original = "{\n  // Why we pin the model\n  \"model\": \"sonnet\", // see ticket\n  \"permissions\": {\n    /* shared */\n    \"allow\": [\"Bash\"],\n  },\n}\n";
+        let updated = apply(original, |d| d["model"] = json!("opus"));
+        assert_eq!(updated, original.replace("\"sonnet\"", "\"opus\""));
+
+        let updated = apply(original, |d| d["permissions"]["deny"] = json!([]));
+        assert_eq!(updated, original.replace("[\"Bash\"],\n", "[\"Bash\"],\n    \"deny\": [],\n"));
+    }
+
+    #[test]
+    fn test_update_removes_commented_member() {
+        let original = "{\n  \"a\": 1,\n  // explains b\n  \"b\": 2, // trailing\n  \"c\": 3\n}";
+        let updated = apply(original, |d| {
+            d.as_object_mut().unwrap().shift_remove("b");
+        });
+        assert_eq!(updated, "{\n  \"a\": 1,\n  \"c\": 3\n}");
+    }
+
+    #[test]
+    fn test_detect_indent() {
+        assert_eq!(detect_indent("{\n    \"a\": 1\n}"), "    ");
+        assert_eq!(detect_indent("{\n\t\"a\": 1\n}"), "\t");
+        assert_eq!(detect_indent("{\"a\": 1}"), "  ");
+    }
+}
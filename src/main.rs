@@ -1,38 +1,139 @@
 mod cli;
+mod conditions;
 mod config;
+mod format;
+mod generator;
+mod git;
+mod history;
+mod interpolate;
+mod journal;
+mod json_edit;
+mod meta;
+mod prefs;
 mod profile;
+mod snapshot;
+mod template;
 mod commands;
 
 use anyhow::Result;
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{AliasAction, BackupAction, Cli, Commands, NotesAction, SnapshotAction, TagAction, TemplateAction, TrashAction};
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
     
     let command = cli.command.unwrap_or(Commands::Interactive);
+    let mutation = command.mutation_summary();
+    let label = command.journal_label();
+    if let Some(label) = &label {
+        history::set_command(label.clone());
+    }
+    let journal = label.and_then(commands::journal_begin);
+    
+    let result = run(command);
+    
+    if let Some((label, before)) = journal {
+        commands::journal_record(label, before, result.is_err());
+    }
+    result?;
+    if let Some(message) = mutation {
+        commands::git_autocommit(&message);
+    }
     
+    Ok(())
+}
+
+fn run(command: Commands) -> Result<()> {
     match command {
         Commands::Interactive => commands::interactive()?,
-        Commands::List => commands::list()?,
+        Commands::List { prefix, long, tags, all } => commands::list(prefix.as_deref(), long, &tags, all)?,
         Commands::Current => commands::current()?,
-        Commands::Use { name } => commands::use_profile(&name)?,
-        Commands::Create { name, from } => commands::create(&name, from.as_deref())?,
+        Commands::Use { name, archived } => commands::use_profile(&name, archived)?,
+        Commands::Create { name, from, template, vars } => {
+            commands::create(&name, from.as_deref(), template.as_deref(), &vars)?
+        }
         Commands::Delete { name, force } => commands::delete(&name, force)?,
+        Commands::Undelete { name, as_name } => commands::undelete(&name, as_name.as_deref())?,
+        Commands::Trash { action } => match action {
+            TrashAction::List => commands::trash_list()?,
+            TrashAction::Empty { older_than, force } => commands::trash_empty(older_than.as_deref(), force)?,
+        },
         Commands::Copy { src, dst } => commands::copy(&src, &dst)?,
-        Commands::Rename { old, new } => commands::rename(&old, &new)?,
-        Commands::Configure { profile, name } => commands::configure(profile.or(name).as_deref())?,
-        Commands::Set { key, value, profile } => commands::set(&key, &value, profile.as_deref())?,
+        Commands::Rename { old, new, force } => commands::rename(&old, &new, force)?,
+        Commands::Configure { profile, name, force } => {
+            commands::configure(profile.or(name).as_deref(), force)?
+        }
+        Commands::Edit { name, force } => commands::edit(name.as_deref(), force)?,
+        Commands::Set { key, value, profile, tags, yes, force } => {
+            commands::set(&key, &value, profile.as_deref(), &tags, yes, force)?
+        }
         Commands::Get { key, profile } => commands::get(&key, profile.as_deref())?,
-        Commands::Unset { key, profile } => commands::unset(&key, profile.as_deref())?,
-        Commands::Export { name } => commands::export(name.as_deref())?,
-        Commands::Import { name } => commands::import(&name)?,
+        Commands::Unset { key, profile, tags, yes, force } => {
+            commands::unset(&key, profile.as_deref(), &tags, yes, force)?
+        }
+        Commands::Explain { key, profile } => commands::explain(&key, profile.as_deref())?,
+        Commands::Log { profile } => commands::log(profile.as_deref())?,
+        Commands::Show { target } => commands::show(&target)?,
+        Commands::Revert { profile, revision, force } => commands::revert(&profile, revision, force)?,
+        Commands::Describe { profile, text, owner, force } => {
+            commands::describe(&profile, text.as_deref(), owner.as_deref(), force)?
+        }
+        Commands::Lock { profile } => commands::lock(&profile)?,
+        Commands::Unlock { profile } => commands::unlock(&profile)?,
+        Commands::Archive { profile, force } => commands::archive(&profile, force)?,
+        Commands::Unarchive { profile, force } => commands::unarchive(&profile, force)?,
+        Commands::Tag { action } => match action {
+            TagAction::Add { profile, tags, force } => commands::tag_add(&profile, &tags, force)?,
+            TagAction::Remove { profile, tags, force } => commands::tag_remove(&profile, &tags, force)?,
+            TagAction::List { profile } => commands::tag_list(profile.as_deref())?,
+        },
+        Commands::Notes { action } => match action {
+            NotesAction::Show { profile } => commands::notes_show(profile.as_deref())?,
+            NotesAction::Edit { profile, force } => commands::notes_edit(profile.as_deref(), force)?,
+        },
+        Commands::Generate { base, vary, name, dry_run, overwrite } => {
+            commands::generate(&base, &vary, name.as_deref(), dry_run, overwrite)?
+        }
+        Commands::Alias { action } => match action {
+            AliasAction::Set { alias, profile } => commands::alias_set(&alias, &profile)?,
+            AliasAction::Remove { alias } => commands::alias_remove(&alias)?,
+            AliasAction::List => commands::alias_list()?,
+        },
+        Commands::Export { name, format, tags, resolved } => {
+            commands::export(name.as_deref(), format, &tags, resolved)?
+        }
+        Commands::Import { name, format, force } => commands::import(name.as_deref(), format, force)?,
         Commands::Diff { profile1, profile2 } => commands::diff(&profile1, &profile2)?,
-        Commands::Backup { name } => commands::backup(name.as_deref())?,
-        Commands::Restore { backup } => commands::restore(&backup)?,
-        Commands::Init => commands::init()?,
+        Commands::Backup { action, name } => match action {
+            None => commands::backup(name.as_deref())?,
+            Some(BackupAction::List) => commands::backup_list()?,
+            Some(BackupAction::Show { name }) => commands::backup_show(&name)?,
+            Some(BackupAction::Delete { name, force }) => commands::backup_delete(&name, force)?,
+            Some(BackupAction::Prune { keep, older_than, prefix, include_named, dry_run }) => {
+                commands::backup_prune(keep, older_than, prefix.as_deref(), include_named, dry_run)?
+            }
+            Some(BackupAction::Retention { keep, older_than, off }) => {
+                commands::backup_retention(keep, older_than, off)?
+            }
+        },
+        Commands::Restore { name, backup, profile, to_profile, switch, force } => {
+            commands::restore(name.as_deref(), backup, profile, to_profile.as_deref(), switch, force)?
+        }
+        Commands::Snapshot { action } => match action {
+            SnapshotAction::Create { name } => commands::snapshot_create(name.as_deref())?,
+            SnapshotAction::List => commands::snapshot_list()?,
+            SnapshotAction::Restore { name, yes, force } => commands::snapshot_restore(&name, yes, force)?,
+        },
+        Commands::Undo { steps, force } => commands::undo(steps, force)?,
+        Commands::Redo { steps, force } => commands::redo(steps, force)?,
+        Commands::Journal => commands::journal()?,
+        Commands::Init { git } => commands::init(git)?,
+        Commands::Git { args } => commands::git(&args)?,
+        Commands::Template { action } => match action {
+            TemplateAction::List => commands::template_list()?,
+            TemplateAction::Show { name } => commands::template_show(&name)?,
+        },
         Commands::Completions { shell } => commands::completions(shell)?,
     }
-    
     Ok(())
 }
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Information about a profile that is not part of its settings. Kept in a
+/// sidecar file under `profiles/.meta/` so it never reaches settings.json.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProfileMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Local>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Local>>,
+    /// Machine-local, so kept in `profiles/.last-used.json` rather than in
+    /// the sidecar file (read from it only for older sidecars).
+    #[serde(skip_serializing)]
+    pub last_used: Option<DateTime<Local>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Locked profiles refuse changes unless `--force` is given.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// Archived profiles are kept but hidden from `list` and the selector.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+}
+
+impl ProfileMeta {
+    /// Add tags, keeping the list sorted and free of duplicates. Returns the
+    /// tags that were not already present.
+    pub fn add_tags(&mut self, tags: &[String]) -> Vec<String> {
+        let mut added: Vec<String> = Vec::new();
+        for tag in tags {
+            if !self.tags.contains(tag) && !added.contains(tag) {
+                added.push(tag.clone());
+            }
+        }
+        self.tags.extend(added.iter().cloned());
+        self.tags.sort();
+        added
+    }
+
+    /// Remove tags, returning the ones that were present.
+    pub fn remove_tags(&mut self, tags: &[String]) -> Vec<String> {
+        let removed: Vec<String> = tags.iter()
+            .filter(|t| self.tags.contains(t))
+            .cloned()
+            .collect();
+        self.tags.retain(|t| !removed.contains(t));
+        removed
+    }
+
+    /// The metadata a copy of this profile starts with: descriptive fields
+    /// carry over, timestamps start fresh and the copy is not locked.
+    pub fn for_copy(&self) -> Self {
+        Self {
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+            owner: self.owner.clone(),
+            notes: self.notes.clone(),
+            ..Self::default()
+        }
+    }
+}
+
+/// How a backup came to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackupOrigin {
+    /// Taken with `ccp backup`, named or not. Earlier versions recorded
+    /// unnamed ones as "automatic".
+    #[serde(alias = "automatic")]
+    Manual,
+    /// Taken by ccp itself, before `ccp restore` overwrote settings.json
+    PreRestore,
+}
+
+impl BackupOrigin {
+    /// Best guess for backups made before origins were recorded, going by
+    /// the names ccp generates.
+    pub fn infer(name: &str) -> Self {
+        if stamped(name, "pre-restore-") {
+            BackupOrigin::PreRestore
+        } else {
+            BackupOrigin::Manual
+        }
+    }
+}
+
+/// Whether ccp made up a backup's name (`backup-…`/`pre-restore-…` plus a
+/// timestamp) rather than the user giving one.
+pub fn is_generated_backup_name(name: &str) -> bool {
+    stamped(name, "backup-") || stamped(name, "pre-restore-")
+}
+
+fn stamped(name: &str, prefix: &str) -> bool {
+    name.strip_prefix(prefix)
+        .is_some_and(|stamp| NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S").is_ok())
+}
+
+impl fmt::Display for BackupOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BackupOrigin::Manual => "manual",
+            BackupOrigin::PreRestore => "pre-restore",
+        })
+    }
+}
+
+/// Sidecar for a backup, stored under `backups/.meta/`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupMeta {
+    pub origin: BackupOrigin,
+    pub created: DateTime<Local>,
+    /// Profile that was active when the backup was taken
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+/// Sidecar for a deleted profile, stored as `trash.json` in its directory
+/// under `profiles/.trash/`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashInfo {
+    pub name: String,
+    pub deleted: DateTime<Local>,
+    /// The profile's file name, e.g. `work.yaml`
+    pub file: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_remove_tags() {
+        let mut meta = ProfileMeta::default();
+        let added = meta.add_tags(&["team".to_string(), "client".to_string(), "team".to_string()]);
+        assert_eq!(added, vec!["team", "client"]);
+        assert_eq!(meta.tags, vec!["client", "team"]);
+
+        assert!(meta.add_tags(&["team".to_string()]).is_empty());
+        assert_eq!(meta.remove_tags(&["team".to_string(), "other".to_string()]), vec!["team"]);
+        assert_eq!(meta.tags, vec!["client"]);
+    }
+
+    #[test]
+    fn test_empty_fields_are_not_serialized() {
+        let meta = ProfileMeta { description: Some("Daily".to_string()), ..Default::default() };
+        assert_eq!(serde_json::to_string(&meta).unwrap(), r#"{"description":"Daily"}"#);
+    }
+
+    #[test]
+    fn test_infer_backup_origin() {
+        assert_eq!(BackupOrigin::infer("backup-20240101-120000"), BackupOrigin::Manual);
+        assert_eq!(BackupOrigin::infer("pre-restore-20240101-120000"), BackupOrigin::PreRestore);
+        assert_eq!(BackupOrigin::infer("backup-before-upgrade"), BackupOrigin::Manual);
+        assert!(is_generated_backup_name("backup-20240101-120000"));
+        assert!(!is_generated_backup_name("backup-before-upgrade"));
+        let old: BackupOrigin = serde_json::from_str(r#""automatic""#).unwrap();
+        assert_eq!(old, BackupOrigin::Manual);
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+/// ccp's own preferences, stored in `~/.claude/ccp.json`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Prefs {
+    /// Applied automatically after every backup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_retention: Option<Retention>,
+    /// Revisions kept per profile; 0 turns history off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_limit: Option<usize>,
+    /// Commands kept for undo; 0 turns the journal off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub journal_limit: Option<usize>,
+}
+
+/// Revisions kept per profile unless `historyLimit` says otherwise.
+pub const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// Undoable commands kept unless `journalLimit` says otherwise.
+pub const DEFAULT_JOURNAL_LIMIT: usize = 50;
+
+impl Prefs {
+    pub fn history_limit(&self) -> usize {
+        self.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT)
+    }
+
+    pub fn journal_limit(&self) -> usize {
+        self.journal_limit.unwrap_or(DEFAULT_JOURNAL_LIMIT)
+    }
+}
+
+/// Which backups to keep. A backup is pruned only if no rule keeps it: it is
+/// not among the `keep` newest, and it is older than `older_than`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Retention {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep: Option<usize>,
+    /// Age such as "30d", "12h" or "2w"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub older_than: Option<String>,
+}
+
+impl Retention {
+    pub fn is_empty(&self) -> bool {
+        self.keep.is_none() && self.older_than.is_none()
+    }
+
+    /// The backups to delete out of `backups` (name, creation time).
+    pub fn select(&self, backups: &[(String, DateTime<Local>)], now: DateTime<Local>) -> Result<Vec<String>> {
+        if self.is_empty() {
+            bail!("A retention rule needs --keep or --older-than");
+        }
+        let max_age = self.older_than.as_deref().map(parse_age).transpose()?;
+
+        let mut newest_first: Vec<&(String, DateTime<Local>)> = backups.iter().collect();
+        newest_first.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+
+        Ok(newest_first.into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.keep.is_none_or(|keep| *i >= keep))
+            .filter(|(_, (_, created))| max_age.is_none_or(|age| now - *created > age))
+            .map(|(_, (name, _))| name.clone())
+            .collect())
+    }
+
+    pub fn describe(&self) -> String {
+        let mut rules = Vec::new();
+        if let Some(keep) = self.keep {
+            rules.push(format!("keep the {} newest", keep));
+        }
+        if let Some(age) = &self.older_than {
+            rules.push(format!("keep anything newer than {}", age));
+        }
+        rules.join(", ")
+    }
+}
+
+/// Parse an age like "45m", "12h", "30d" or "2w".
+pub fn parse_age(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let n: i64 = number.parse()
+        .with_context(|| format!("Invalid age '{}': expected e.g. 30d, 12h or 2w", text))?;
+    let age = match unit {
+        "m" => Duration::try_minutes(n),
+        "h" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        "w" => Duration::try_weeks(n),
+        _ => bail!("Invalid age '{}': unit must be m, h, d or w", text),
+    };
+    age.with_context(|| format!("Invalid age '{}': age too large", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d").unwrap(), Duration::days(30));
+        assert_eq!(parse_age("2w").unwrap(), Duration::days(14));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("5y").is_err());
+        assert!(parse_age("99999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_select_keeps_anything_a_rule_protects() {
+        let now = Local::now();
+        let backups: Vec<(String, DateTime<Local>)> = [1, 5, 40, 60, 90]
+            .iter()
+            .map(|days| (format!("b{}", days), now - Duration::days(*days)))
+            .collect();
+
+        let keep = Retention { keep: Some(2), older_than: None };
+        assert_eq!(keep.select(&backups, now).unwrap(), ["b40", "b60", "b90"]);
+
+        let age = Retention { keep: None, older_than: Some("30d".to_string()) };
+        assert_eq!(age.select(&backups, now).unwrap(), ["b40", "b60", "b90"]);
+
+        let both = Retention { keep: Some(4), older_than: Some("30d".to_string()) };
+        assert_eq!(both.select(&backups, now).unwrap(), ["b90"]);
+
+        assert!(Retention::default().select(&backups, now).is_err());
+    }
+}
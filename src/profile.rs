@@ -1,40 +1,108 @@
 use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
 use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::config::Config;
+use crate::config::{validate_name, validate_profile_name, Config};
+use crate::conditions::{self, Machine};
+use crate::format::Format;
+use crate::generator::Generator;
+use crate::history::{self, Revision};
+use crate::interpolate;
+use crate::json_edit;
+use crate::meta::{BackupMeta, BackupOrigin, ProfileMeta, TrashInfo};
+use crate::prefs::Prefs;
 
 pub struct ProfileManager {
     pub config: Config,
+    /// Let changes through to locked profiles (`--force`)
+    force: bool,
 }
 
 impl ProfileManager {
     pub fn new() -> Result<Self> {
         Ok(Self {
             config: Config::new()?,
+            force: false,
         })
     }
     
+    /// Allow changing locked profiles, for commands given `--force`.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+    
+    /// Refuse to change a locked profile unless forced. Every write to a
+    /// profile's content or metadata goes through this.
+    pub fn check_unlocked(&self, name: &str) -> Result<()> {
+        if !self.force && self.load_meta(name)?.locked {
+            bail!("Profile '{}' is locked. Use --force to change it anyway, or 'ccp unlock {}'.", name, name);
+        }
+        Ok(())
+    }
+    
+    /// All profile names, including namespaced ones like `work/client-a`.
     pub fn list_profiles(&self) -> Result<Vec<String>> {
-        let mut profiles = Vec::new();
+        let mut profiles = BTreeSet::new();
         
         if self.config.profiles_dir.exists() {
-            for entry in fs::read_dir(&self.config.profiles_dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.extension().map_or(false, |e| e == "json") {
-                    if let Some(name) = path.file_stem() {
-                        profiles.push(name.to_string_lossy().to_string());
-                    }
-                }
-            }
+            self.collect_profiles(&self.config.profiles_dir, "", &mut profiles)?;
         }
         
-        profiles.sort();
+        Ok(profiles.into_iter().collect())
+    }
+    
+    /// Profiles that are not archived, as offered by `list` and the selector.
+    pub fn list_unarchived_profiles(&self) -> Result<Vec<String>> {
+        let mut profiles = Vec::new();
+        for name in self.list_profiles()? {
+            if !self.load_meta(&name)?.archived {
+                profiles.push(name);
+            }
+        }
         Ok(profiles)
     }
     
+    fn collect_profiles(&self, dir: &Path, prefix: &str, profiles: &mut BTreeSet<String>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            
+            // Dot-entries hold ccp's own state (.current and friends)
+            if file_name.starts_with('.') {
+                continue;
+            }
+            
+            if path.is_dir() {
+                self.collect_profiles(&path, &format!("{}{}/", prefix, file_name), profiles)?;
+            } else if let Some(name) = file_name.strip_suffix(".profile.sh") {
+                profiles.insert(format!("{}{}", prefix, name));
+            } else if Format::from_path(&path).is_some() {
+                if let Some(name) = path.file_stem() {
+                    profiles.insert(format!("{}{}", prefix, name.to_string_lossy()));
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Profiles that exist as more than one file, e.g. `work.json` and `work.yaml`.
+    pub fn list_conflicts(&self) -> Result<Vec<(String, Vec<PathBuf>)>> {
+        let mut conflicts = Vec::new();
+        for name in self.list_profiles()? {
+            let files = self.profile_files(&name);
+            if files.len() > 1 {
+                conflicts.push((name, files.into_iter().map(|(path, _)| path).collect()));
+            }
+        }
+        Ok(conflicts)
+    }
+    
     pub fn list_backups(&self) -> Result<Vec<String>> {
         let mut backups = Vec::new();
         
@@ -42,7 +110,7 @@ impl ProfileManager {
             for entry in fs::read_dir(&self.config.backups_dir)? {
                 let entry = entry?;
                 let path = entry.path();
-                if path.extension().map_or(false, |e| e == "json") {
+                if path.extension().is_some_and(|e| e == "json") {
                     if let Some(name) = path.file_stem() {
                         backups.push(name.to_string_lossy().to_string());
                     }
@@ -54,6 +122,60 @@ impl ProfileManager {
         Ok(backups)
     }
     
+    /// Every backup with its metadata.
+    pub fn list_backup_metas(&self) -> Result<Vec<(String, BackupMeta)>> {
+        self.list_backups()?
+            .into_iter()
+            .map(|name| Ok((name.clone(), self.load_backup_meta(&name)?)))
+            .collect()
+    }
+    
+    /// A backup's metadata. Backups from before metadata was recorded get
+    /// their file's modification time and an origin guessed from the name.
+    pub fn load_backup_meta(&self, name: &str) -> Result<BackupMeta> {
+        validate_name("Backup", name)?;
+        let meta_path = self.config.backup_meta_path(name);
+        if meta_path.exists() {
+            return serde_json::from_value(self.load_json(&meta_path)?)
+                .with_context(|| format!("Invalid backup metadata in {}", meta_path.display()));
+        }
+        
+        let path = self.config.backup_path(name);
+        let modified = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(BackupMeta {
+            origin: BackupOrigin::infer(name),
+            created: DateTime::<Local>::from(modified),
+            profile: None,
+        })
+    }
+    
+    pub fn delete_backup(&self, name: &str) -> Result<()> {
+        validate_name("Backup", name)?;
+        let path = self.config.backup_path(name);
+        fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))?;
+        let meta_path = self.config.backup_meta_path(name);
+        if meta_path.exists() {
+            fs::remove_file(&meta_path)
+                .with_context(|| format!("Failed to delete {}", meta_path.display()))?;
+        }
+        Ok(())
+    }
+    
+    pub fn load_prefs(&self) -> Result<Prefs> {
+        let path = &self.config.prefs_file;
+        if !path.exists() {
+            return Ok(Prefs::default());
+        }
+        serde_json::from_value(self.load_json(path)?)
+            .with_context(|| format!("Invalid preferences in {}", path.display()))
+    }
+    
+    pub fn save_prefs(&self, prefs: &Prefs) -> Result<()> {
+        self.save_json(&self.config.prefs_file, &serde_json::to_value(prefs)?)
+    }
+    
     pub fn get_current_profile(&self) -> Result<Option<String>> {
         if self.config.current_profile_file.exists() {
             let content = fs::read_to_string(&self.config.current_profile_file)?;
@@ -64,62 +186,755 @@ impl ProfileManager {
     }
     
     pub fn set_current_profile(&self, name: &str) -> Result<()> {
+        validate_profile_name(name)?;
         fs::write(&self.config.current_profile_file, name)?;
         Ok(())
     }
     
+    /// Forget the current profile, for when settings.json matches none.
+    pub fn clear_current_profile(&self) -> Result<()> {
+        if self.config.current_profile_file.exists() {
+            fs::remove_file(&self.config.current_profile_file)?;
+        }
+        Ok(())
+    }
+    
+    /// Turn what the user typed into an existing profile name. Tries, in
+    /// order: the exact name, an alias, a case-insensitive match, and a unique
+    /// prefix. On a miss the error suggests the closest names. Archived
+    /// profiles are only matched by their exact name or an alias.
+    pub fn resolve_name(&self, input: &str) -> Result<String> {
+        self.resolve_name_among(input, false)
+    }
+    
+    /// Like `resolve_name`, with archived profiles matched like any other.
+    pub fn resolve_name_with_archived(&self, input: &str) -> Result<String> {
+        self.resolve_name_among(input, true)
+    }
+    
+    fn resolve_name_among(&self, input: &str, archived: bool) -> Result<String> {
+        validate_profile_name(input)?;
+        if self.profile_exists(input) {
+            return Ok(input.to_string());
+        }
+        
+        let aliases = self.load_aliases()?;
+        if let Some(target) = aliases.get(input) {
+            if self.profile_exists(target) {
+                return Ok(target.clone());
+            }
+            bail!("Alias '{}' points to '{}', which does not exist", input, target);
+        }
+        
+        let profiles = if archived { self.list_profiles()? } else { self.list_unarchived_profiles()? };
+        let lower = input.to_lowercase();
+        
+        let exact: Vec<&String> = profiles.iter().filter(|p| p.to_lowercase() == lower).collect();
+        if let [only] = exact[..] {
+            return Ok(only.clone());
+        }
+        
+        let prefixed: Vec<&str> = profiles.iter()
+            .filter(|p| p.to_lowercase().starts_with(&lower))
+            .map(|p| p.as_str())
+            .collect();
+        match prefixed[..] {
+            [only] => return Ok(only.to_string()),
+            [] => {}
+            _ => bail!("'{}' matches several profiles: {}", input, prefixed.join(", ")),
+        }
+        
+        let mut candidates: Vec<(usize, &String)> = profiles.iter()
+            .chain(aliases.keys())
+            .map(|p| (strsim::levenshtein(&lower, &p.to_lowercase()), p))
+            .filter(|(distance, p)| *distance <= 2.max(p.len() / 3))
+            .collect();
+        candidates.sort();
+        
+        if candidates.is_empty() {
+            bail!("Profile '{}' does not exist. Use 'ccp list' to see available profiles.", input);
+        }
+        let suggestions: Vec<String> = candidates.iter()
+            .take(3)
+            .map(|(_, p)| format!("'{}'", p))
+            .collect();
+        bail!("Profile '{}' does not exist. Did you mean {}?", input, suggestions.join(" or "));
+    }
+    
+    /// Like `resolve_name`, but only the exact name or an alias is accepted,
+    /// for commands that delete or move a profile. A looser match is only
+    /// suggested.
+    pub fn resolve_exact_name(&self, input: &str) -> Result<String> {
+        validate_profile_name(input)?;
+        if self.profile_exists(input) {
+            return Ok(input.to_string());
+        }
+        if self.load_aliases()?.contains_key(input) {
+            return self.resolve_name(input);
+        }
+        let resolved = self.resolve_name(input)?;
+        bail!(
+            "Profile '{}' does not exist. Did you mean '{}'? Give the exact name for this command.",
+            input,
+            resolved,
+        );
+    }
+    
+    pub fn load_aliases(&self) -> Result<BTreeMap<String, String>> {
+        if !self.config.aliases_file.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let data = self.load_json(&self.config.aliases_file)?;
+        serde_json::from_value(data).context("Aliases file must map alias names to profile names")
+    }
+    
+    pub fn save_aliases(&self, aliases: &BTreeMap<String, String>) -> Result<()> {
+        self.save_json(&self.config.aliases_file, &serde_json::to_value(aliases)?)
+    }
+    
+    /// Point aliases for `old` at `new`, or drop them when the profile is
+    /// deleted. Returns the aliases that were touched.
+    pub fn retarget_aliases(&self, old: &str, new: Option<&str>) -> Result<Vec<String>> {
+        let mut aliases = self.load_aliases()?;
+        let affected: Vec<String> = aliases.iter()
+            .filter(|(_, target)| target.as_str() == old)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        
+        if affected.is_empty() {
+            return Ok(affected);
+        }
+        for alias in &affected {
+            match new {
+                Some(new) => { aliases.insert(alias.clone(), new.to_string()); }
+                None => { aliases.remove(alias); }
+            }
+        }
+        self.save_aliases(&aliases)?;
+        Ok(affected)
+    }
+    
     pub fn profile_exists(&self, name: &str) -> bool {
-        self.config.profile_path(name).exists()
+        validate_profile_name(name).is_ok()
+            && (!self.profile_files(name).is_empty() || self.is_script_profile(name))
+    }
+    
+    /// Whether the profile is a `name.profile.sh` script rather than a file.
+    pub fn is_script_profile(&self, name: &str) -> bool {
+        self.profile_files(name).is_empty() && self.config.script_path(name).is_file()
+    }
+    
+    fn profile_files(&self, name: &str) -> Vec<(PathBuf, Format)> {
+        let mut files = Vec::new();
+        for format in Format::ALL {
+            for ext in format.extensions() {
+                let path = self.config.profile_path_ext(name, ext);
+                if path.is_file() {
+                    files.push((path, format));
+                }
+            }
+        }
+        files
+    }
+    
+    /// The file backing a profile and its format, or an error if the name
+    /// is ambiguous because files exist in several formats.
+    pub fn profile_file(&self, name: &str) -> Result<Option<(PathBuf, Format)>> {
+        validate_profile_name(name)?;
+        let mut files = self.profile_files(name);
+        if files.len() > 1 {
+            let paths: Vec<String> = files.iter()
+                .map(|(p, _)| p.file_name().unwrap_or_default().to_string_lossy().to_string())
+                .collect();
+            bail!("Profile '{}' exists in more than one format ({}). Remove all but one.", name, paths.join(", "));
+        }
+        Ok(files.pop())
+    }
+    
+    pub fn profile_format(&self, name: &str) -> Result<Option<Format>> {
+        Ok(self.profile_file(name)?.map(|(_, format)| format))
     }
     
     pub fn load_profile(&self, name: &str) -> Result<Value> {
-        let path = self.config.profile_path(name);
-        self.load_json(&path)
+        if self.is_script_profile(name) {
+            // Names are validated, so the path needs no shell quoting
+            return Ok(serde_json::json!({ "generator": format!("./{}.profile.sh", name) }));
+        }
+        let (path, format) = self.profile_file(name)?
+            .with_context(|| format!("Profile '{}' does not exist", name))?;
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        format.parse(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
     }
     
+    /// Save a profile in the format of its existing file, or as JSON if new.
     pub fn save_profile(&self, name: &str, data: &Value) -> Result<()> {
-        let path = self.config.profile_path(name);
-        self.save_json(&path, data)
+        let format = self.profile_format(name)?.unwrap_or(Format::Json);
+        self.save_profile_as(name, data, format)
+    }
+    
+    pub fn save_profile_as(&self, name: &str, data: &Value, format: Format) -> Result<()> {
+        self.check_unlocked(name)?;
+        if self.is_script_profile(name) {
+            bail!(
+                "Profile '{}' is generated by {}; change the script instead",
+                name,
+                self.config.script_path(name).display()
+            );
+        }
+        let path = match self.profile_file(name)? {
+            Some((path, existing)) if existing == format => path,
+            Some((_, existing)) => bail!("Profile '{}' is stored as {}, not {}", name, existing, format),
+            None if format == Format::Json => self.config.profile_path(name),
+            None => self.config.profile_path_ext(name, format.extension()),
+        };
+        
+        let is_new = !path.exists();
+        if !is_new {
+            let previous = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            self.record_revision(name, format, previous, data)?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        
+        match format {
+            Format::Json => self.save_json(&path, data)?,
+            _ => {
+                fs::write(&path, format.to_string(data)?)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+        
+        let mut meta = self.load_meta(name)?;
+        let now = Local::now();
+        if is_new {
+            meta.created = Some(now);
+            if meta.owner.is_none() {
+                meta.owner = env::var("USER").or_else(|_| env::var("USERNAME")).ok();
+            }
+        }
+        meta.updated = Some(now);
+        self.save_meta(name, &meta)
+    }
+    
+    /// Keep a profile's `previous` file content as a revision before `data`
+    /// replaces it, then drop the oldest revisions beyond the history limit.
+    fn record_revision(&self, name: &str, format: Format, previous: String, data: &Value) -> Result<()> {
+        let limit = self.load_prefs()?.history_limit();
+        let old = format.parse(&previous).unwrap_or(Value::Null);
+        if limit == 0 || &old == data {
+            return Ok(());
+        }
+        
+        let mut numbers = self.revision_numbers(name)?;
+        let number = numbers.last().map_or(1, |n| n + 1);
+        let revision = Revision {
+            time: Local::now(),
+            command: history::current_command(),
+            summary: history::summarize(&old, data),
+            format,
+            content: previous,
+        };
+        let path = self.config.revision_path(name, number);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        self.save_json(&path, &serde_json::to_value(&revision)?)?;
+        
+        numbers.push(number);
+        let excess = numbers.len().saturating_sub(limit);
+        for old in &numbers[..excess] {
+            fs::remove_file(self.config.revision_path(name, *old)).ok();
+        }
+        Ok(())
+    }
+    
+    /// Revision numbers of a profile, oldest first.
+    pub fn revision_numbers(&self, name: &str) -> Result<Vec<u32>> {
+        let path = self.config.revision_path(name, 0);
+        let (Some(dir), Some(base)) = (path.parent(), name.rsplit('/').next()) else {
+            return Ok(Vec::new());
+        };
+        let prefix = format!("{}@", base);
+        let mut numbers = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let file_name = entry?.file_name().to_string_lossy().to_string();
+                let number = file_name.strip_prefix(&prefix)
+                    .and_then(|rest| rest.strip_suffix(".json"))
+                    .and_then(|n| n.parse::<u32>().ok());
+                numbers.extend(number);
+            }
+        }
+        numbers.sort();
+        Ok(numbers)
+    }
+    
+    pub fn load_revision(&self, name: &str, number: u32) -> Result<Revision> {
+        let path = self.config.revision_path(name, number);
+        if !path.exists() {
+            bail!("Profile '{}' has no revision {}. Use 'ccp log {}' to see its history.", name, number, name);
+        }
+        serde_json::from_value(self.load_json(&path)?)
+            .with_context(|| format!("Invalid revision in {}", path.display()))
+    }
+    
+    /// Save `data` as the profile's content in `format`, removing an existing
+    /// file in another format first. Metadata is kept.
+    pub fn replace_profile(&self, name: &str, data: &Value, format: Format) -> Result<()> {
+        self.check_unlocked(name)?;
+        if let Some((path, existing)) = self.profile_file(name)? {
+            if existing != format {
+                let previous = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                self.record_revision(name, existing, previous, data)?;
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        self.save_profile_as(name, data, format)
     }
     
-    pub fn delete_profile(&self, name: &str) -> Result<()> {
-        let path = self.config.profile_path(name);
+    /// Move a profile, with its metadata and history, to `profiles/.trash/`.
+    /// Returns the id of its trash entry.
+    pub fn delete_profile(&self, name: &str) -> Result<String> {
+        self.check_unlocked(name)?;
+        let path = self.existing_profile_path(name)?;
+        let deleted = Local::now();
+        let base_id = format!("{}-{}", deleted.format("%Y%m%d-%H%M%S"), name.replace('/', "_"));
+        let mut id = base_id.clone();
+        let mut n = 1;
+        while self.config.trash_path(&id).exists() {
+            n += 1;
+            id = format!("{}-{}", base_id, n);
+        }
+        
+        let dir = self.config.trash_path(&id);
+        let file = path.file_name()
+            .context("Profile path has no file name")?
+            .to_string_lossy()
+            .to_string();
+        move_file(&path, &dir.join(&file)).context("Failed to move profile to the trash")?;
+        let info = TrashInfo { name: name.to_string(), deleted, file: file.clone() };
+        let saved = serde_json::to_value(&info)
+            .map_err(anyhow::Error::from)
+            .and_then(|info| self.save_json(&dir.join("trash.json"), &info));
+        if let Err(e) = saved {
+            // Without trash.json the entry could not be listed or restored
+            fs::rename(dir.join(&file), &path).ok();
+            fs::remove_dir_all(&dir).ok();
+            return Err(e).context("Failed to record the profile in the trash");
+        }
+        remove_empty_dirs(&path, &self.config.profiles_dir);
+        
+        let meta_path = self.config.meta_path(name);
+        if meta_path.exists() {
+            move_file(&meta_path, &dir.join("meta.json")).context("Failed to move profile metadata")?;
+            remove_empty_dirs(&meta_path, &self.config.meta_dir);
+        }
+        self.set_last_used(name, None)?;
+        for number in self.revision_numbers(name)? {
+            let path = self.config.revision_path(name, number);
+            move_file(&path, &dir.join("history").join(format!("{}.json", number)))
+                .context("Failed to move profile history")?;
+            remove_empty_dirs(&path, &self.config.history_dir);
+        }
+        Ok(id)
+    }
+    
+    fn existing_profile_path(&self, name: &str) -> Result<PathBuf> {
+        Ok(match self.profile_file(name)? {
+            Some((path, _)) => path,
+            None if self.is_script_profile(name) => self.config.script_path(name),
+            None => bail!("Profile '{}' does not exist", name),
+        })
+    }
+    
+    /// Remove a profile's file and metadata for good.
+    fn remove_profile(&self, name: &str) -> Result<()> {
+        let path = self.existing_profile_path(name)?;
         fs::remove_file(&path).context("Failed to delete profile")?;
+        remove_empty_dirs(&path, &self.config.profiles_dir);
+        
+        let meta_path = self.config.meta_path(name);
+        if meta_path.exists() {
+            fs::remove_file(&meta_path).context("Failed to delete profile metadata")?;
+            remove_empty_dirs(&meta_path, &self.config.meta_dir);
+        }
+        self.set_last_used(name, None)
+    }
+    
+    /// Deleted profiles, newest first, with their trash ids.
+    pub fn list_trash(&self) -> Result<Vec<(String, TrashInfo)>> {
+        let mut trash = Vec::new();
+        if self.config.trash_dir.is_dir() {
+            for entry in fs::read_dir(&self.config.trash_dir)? {
+                let entry = entry?;
+                let info_path = entry.path().join("trash.json");
+                if !info_path.exists() {
+                    continue;
+                }
+                let info: TrashInfo = serde_json::from_value(self.load_json(&info_path)?)
+                    .with_context(|| format!("Invalid trash entry {}", info_path.display()))?;
+                trash.push((entry.file_name().to_string_lossy().to_string(), info));
+            }
+        }
+        trash.sort_by(|a, b| b.1.deleted.cmp(&a.1.deleted).then_with(|| b.0.cmp(&a.0)));
+        Ok(trash)
+    }
+    
+    /// Put a deleted profile back, as `name` if given. Returns its name.
+    pub fn undelete(&self, id: &str, info: &TrashInfo, name: Option<&str>) -> Result<String> {
+        let name = name.unwrap_or(&info.name);
+        validate_profile_name(name)?;
+        if self.profile_exists(name) {
+            bail!("Profile '{}' already exists. Restore under another name with --as <name>.", name);
+        }
+        
+        let dir = self.config.trash_path(id);
+        let target = if info.file.ends_with(".profile.sh") {
+            self.config.script_path(name)
+        } else {
+            let ext = Path::new(&info.file).extension()
+                .and_then(|e| e.to_str())
+                .context("Trashed profile has no file extension")?;
+            self.config.profile_path_ext(name, ext)
+        };
+        move_file(&dir.join(&info.file), &target).context("Failed to restore profile from the trash")?;
+        
+        let meta = dir.join("meta.json");
+        if meta.exists() {
+            move_file(&meta, &self.config.meta_path(name)).context("Failed to restore profile metadata")?;
+        }
+        let history = dir.join("history");
+        if history.is_dir() {
+            for entry in fs::read_dir(&history)? {
+                let path = entry?.path();
+                let number = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u32>().ok());
+                if let Some(number) = number {
+                    move_file(&path, &self.config.revision_path(name, number))
+                        .context("Failed to restore profile history")?;
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).context("Failed to clean up the trash entry")?;
+        Ok(name.to_string())
+    }
+    
+    pub fn purge_trash(&self, id: &str) -> Result<()> {
+        fs::remove_dir_all(self.config.trash_path(id))
+            .with_context(|| format!("Failed to remove trash entry '{}'", id))
+    }
+    
+    /// Copy a profile in its own format. Descriptive metadata comes along;
+    /// timestamps start fresh.
+    pub fn copy_profile(&self, src: &str, dst: &str) -> Result<()> {
+        let data = self.load_profile(src)?;
+        let format = self.profile_format(src)?.unwrap_or(Format::Json);
+        self.save_meta(dst, &self.load_meta(src)?.for_copy())?;
+        self.save_profile_as(dst, &data, format)
+    }
+    
+    /// Move a profile, with its metadata, to a new name.
+    pub fn rename_profile(&self, old: &str, new: &str) -> Result<()> {
+        self.check_unlocked(old)?;
+        if self.is_script_profile(old) {
+            let target = self.config.script_path(new);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            let meta = self.load_meta(old)?;
+            fs::rename(self.config.script_path(old), &target).context("Failed to rename profile script")?;
+            remove_empty_dirs(&self.config.script_path(old), &self.config.profiles_dir);
+            self.save_meta(new, &meta)?;
+            let meta_path = self.config.meta_path(old);
+            if meta_path.exists() {
+                fs::remove_file(&meta_path).context("Failed to move profile metadata")?;
+                remove_empty_dirs(&meta_path, &self.config.meta_dir);
+            }
+            return self.set_last_used(old, None);
+        }
+        
+        let data = self.load_profile(old)?;
+        let format = self.profile_format(old)?.unwrap_or(Format::Json);
+        let meta = self.load_meta(old)?;
+        
+        self.save_profile_as(new, &data, format)?;
+        self.move_history(old, new)?;
+        self.remove_profile(old)?;
+        
+        // Renaming is not an edit: keep the original timestamps
+        self.save_meta(new, &meta)
+    }
+    
+    fn move_history(&self, old: &str, new: &str) -> Result<()> {
+        for number in self.revision_numbers(old)? {
+            let from = self.config.revision_path(old, number);
+            let to = self.config.revision_path(new, number);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            fs::rename(&from, &to).context("Failed to move profile history")?;
+            remove_empty_dirs(&from, &self.config.history_dir);
+        }
         Ok(())
     }
     
+    /// Profiles carrying every one of `tags`, in list order.
+    pub fn profiles_with_tags(&self, tags: &[String]) -> Result<Vec<String>> {
+        let mut matching = Vec::new();
+        for profile in self.list_profiles()? {
+            let meta = self.load_meta(&profile)?;
+            if tags.iter().all(|t| meta.tags.contains(t)) {
+                matching.push(profile);
+            }
+        }
+        Ok(matching)
+    }
+    
+    pub fn load_meta(&self, name: &str) -> Result<ProfileMeta> {
+        validate_profile_name(name)?;
+        let path = self.config.meta_path(name);
+        if !path.exists() {
+            let last_used = self.load_usage()?.remove(name);
+            return Ok(ProfileMeta { last_used, ..ProfileMeta::default() });
+        }
+        let data = self.load_json(&path)?;
+        let mut meta: ProfileMeta = serde_json::from_value(data)
+            .with_context(|| format!("Invalid metadata in {}", path.display()))?;
+        if let Some(last_used) = self.load_usage()?.remove(name) {
+            meta.last_used = Some(last_used);
+        }
+        Ok(meta)
+    }
+    
+    /// Locking, unlocking and recording use are allowed on a locked
+    /// profile; any other change needs `force`.
+    pub fn save_meta(&self, name: &str, meta: &ProfileMeta) -> Result<()> {
+        validate_profile_name(name)?;
+        let stored = self.load_meta(name)?;
+        if stored.locked {
+            let unchanged = ProfileMeta { locked: true, last_used: stored.last_used, ..meta.clone() };
+            if unchanged != stored {
+                self.check_unlocked(name)?;
+            }
+        }
+        self.set_last_used(name, meta.last_used)?;
+        let path = self.config.meta_path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        self.save_json(&path, &serde_json::to_value(meta)?)
+    }
+    
+    /// When each profile was last switched to on this machine.
+    fn load_usage(&self) -> Result<BTreeMap<String, DateTime<Local>>> {
+        if !self.config.usage_file.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let data = self.load_json(&self.config.usage_file)?;
+        serde_json::from_value(data)
+            .with_context(|| format!("Invalid usage data in {}", self.config.usage_file.display()))
+    }
+    
+    fn set_last_used(&self, name: &str, last_used: Option<DateTime<Local>>) -> Result<()> {
+        let mut usage = self.load_usage()?;
+        let changed = match last_used {
+            Some(time) => usage.insert(name.to_string(), time) != Some(time),
+            None => usage.remove(name).is_some(),
+        };
+        if !changed {
+            return Ok(());
+        }
+        self.save_json(&self.config.usage_file, &serde_json::to_value(&usage)?)
+    }
+    
     pub fn load_settings(&self) -> Result<Value> {
         self.load_json(&self.config.settings_file)
     }
     
+    /// Variables from the global vars file, available to every profile.
+    pub fn load_global_vars(&self) -> Result<BTreeMap<String, String>> {
+        let path = &self.config.vars_file;
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        serde_json::from_value(self.load_json(path)?)
+            .with_context(|| format!("{} must map variable names to strings", path.display()))
+    }
+    
+    /// A profile as it is applied to settings.json: matching `when` blocks
+    /// are merged in, the `vars` map is dropped and `${VAR}` placeholders are
+    /// expanded from it, then from the global vars file, then from the
+    /// environment.
+    pub fn resolve_profile(&self, data: &Value) -> Result<Value> {
+        let mut data = data.clone();
+        if let Some(generated) = self.take_generated(&mut data)? {
+            conditions::deep_merge(&mut data, &generated);
+        }
+        conditions::apply(&mut data, &Machine::current())?;
+        let local: BTreeMap<String, String> = match data.as_object_mut().and_then(|m| m.shift_remove("vars")) {
+            Some(vars) => serde_json::from_value(vars).context("'vars' must map variable names to strings")?,
+            None => BTreeMap::new(),
+        };
+        let global = self.load_global_vars()?;
+        
+        interpolate::expand(&data, &|name: &str| {
+            local.get(name)
+                .or_else(|| global.get(name))
+                .cloned()
+                .or_else(|| env::var(name).ok())
+        })
+    }
+    
+    /// Remove the `generator` field from a profile and run it, returning the
+    /// settings it printed.
+    pub fn take_generated(&self, data: &mut Value) -> Result<Option<Value>> {
+        let Some(spec) = data.as_object_mut().and_then(|m| m.shift_remove("generator")) else {
+            return Ok(None);
+        };
+        let generated = Generator::parse(&spec)?.run(&self.config.profiles_dir)?;
+        self.validate_profile(&generated).context("Generator output is not a valid profile")?;
+        Ok(Some(generated))
+    }
+    
+    /// Write a profile to settings.json with its variables resolved.
+    pub fn apply_profile(&self, data: &Value) -> Result<()> {
+        self.save_settings(&self.resolve_profile(data)?)
+    }
+    
+    /// Claude Code reads settings.json as strict JSON, so comments or
+    /// trailing commas left over from an earlier hand edit are never kept.
     pub fn save_settings(&self, data: &Value) -> Result<()> {
-        self.save_json(&self.config.settings_file, data)
+        let path = &self.config.settings_file;
+        let content = fs::read_to_string(path)
+            .ok()
+            .and_then(|original| json_edit::update(&original, data).ok())
+            .filter(|updated| serde_json::from_str::<Value>(updated).is_ok());
+
+        let content = match content {
+            Some(content) => content,
+            None => serde_json::to_string_pretty(data)?,
+        };
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+    
+    pub fn backup_exists(&self, name: &str) -> bool {
+        validate_name("Backup", name).is_ok() && self.config.backup_path(name).is_file()
     }
     
     pub fn load_backup(&self, name: &str) -> Result<Value> {
+        validate_name("Backup", name)?;
         let path = self.config.backup_path(name);
         self.load_json(&path)
     }
     
-    pub fn save_backup(&self, name: &str, data: &Value) -> Result<()> {
+    /// Save a backup along with where it came from and the active profile.
+    pub fn save_backup(&self, name: &str, data: &Value, origin: BackupOrigin) -> Result<()> {
+        validate_name("Backup", name)?;
         let path = self.config.backup_path(name);
-        self.save_json(&path, data)
+        self.save_json(&path, data)?;
+        
+        let meta = BackupMeta {
+            origin,
+            created: Local::now(),
+            profile: self.get_current_profile()?,
+        };
+        let meta_path = self.config.backup_meta_path(name);
+        if let Some(parent) = meta_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(&meta_path, serde_json::to_string_pretty(&meta)?)
+            .with_context(|| format!("Failed to write {}", meta_path.display()))
     }
     
     fn load_json(&self, path: &Path) -> Result<Value> {
         let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read {}", path.display()))?;
-        serde_json::from_str(&content)
+        json_edit::parse(&content)
             .with_context(|| format!("Failed to parse JSON from {}", path.display()))
     }
     
     fn save_json(&self, path: &Path, data: &Value) -> Result<()> {
-        let content = serde_json::to_string_pretty(data)?;
+        // Rewrite existing files in place so key order and formatting survive
+        let content = match fs::read_to_string(path) {
+            Ok(original) => json_edit::update(&original, data)
+                .or_else(|_| serde_json::to_string_pretty(data))?,
+            Err(_) => serde_json::to_string_pretty(data)?,
+        };
         fs::write(path, content)
             .with_context(|| format!("Failed to write {}", path.display()))?;
         Ok(())
     }
     
+    /// Check the shape of the settings keys ccp knows about, so obviously
+    /// broken profiles never reach settings.json.
+    pub fn validate_profile(&self, data: &Value) -> Result<()> {
+        let map = match data {
+            Value::Object(map) => map,
+            _ => bail!("Profile must be a JSON object"),
+        };
+
+        for (key, value) in map {
+            match key.as_str() {
+                "model" | "$schema" | "apiKeyHelper" | "outputStyle" if !value.is_string() => {
+                    bail!("'{}' must be a string", key);
+                }
+                "alwaysThinkingEnabled" | "includeCoAuthoredBy" if !value.is_boolean() => {
+                    bail!("'{}' must be true or false", key);
+                }
+                "env" | "vars" => {
+                    let env = value.as_object()
+                        .with_context(|| format!("'{}' must be an object", key))?;
+                    for (name, v) in env {
+                        if !v.is_string() {
+                            bail!("'{}.{}' must be a string", key, name);
+                        }
+                    }
+                }
+                "permissions" => {
+                    let perms = value.as_object()
+                        .with_context(|| format!("'{}' must be an object", key))?;
+                    for list in ["allow", "deny", "ask"] {
+                        if let Some(rules) = perms.get(list) {
+                            let ok = rules.as_array()
+                                .is_some_and(|r| r.iter().all(|v| v.is_string()));
+                            if !ok {
+                                bail!("'permissions.{}' must be a list of strings", list);
+                            }
+                        }
+                    }
+                }
+                "generator" => {
+                    Generator::parse(value)?;
+                }
+                "when" => {
+                    for (i, block) in conditions::parse_blocks(value)?.iter().enumerate() {
+                        self.validate_profile(&block.settings)
+                            .with_context(|| format!("In 'when[{}]'", i))?;
+                    }
+                }
+                "hooks" if !value.is_object() => {
+                    bail!("'{}' must be an object", key);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn get_value(&self, data: &Value, key: &str) -> Option<Value> {
         let parts: Vec<&str> = key.split('.').collect();
         let mut current = data;
@@ -176,7 +991,7 @@ impl ProfileManager {
             if i == parts.len() - 1 {
                 match current {
                     Value::Object(map) => {
-                        return Ok(map.remove(*part).is_some());
+                        return Ok(map.shift_remove(*part).is_some());
                     }
                     _ => return Ok(false),
                 }
@@ -198,6 +1013,27 @@ impl ProfileManager {
     }
 }
 
+/// Rename `from` to `to`, creating `to`'s directory first.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::rename(from, to).with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))
+}
+
+/// Remove directories left empty after `path` was removed, walking up but
+/// never removing `root` itself.
+pub fn remove_empty_dirs(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d == root || !d.starts_with(root) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -267,7 +1103,7 @@ mod tests {
         let mut data = json!({ "model": "sonnet-4", "other": "value" });
         let pm = ProfileManager::new().unwrap();
         assert!(pm.unset_value(&mut data, "model").unwrap());
-        assert!(!data.get("model").is_some());
+        assert!(data.get("model").is_none());
         assert_eq!(data["other"], json!("value"));
     }
 
@@ -284,7 +1120,17 @@ mod tests {
         let mut data = json!({ "env": { "ANTHROPIC_BASE_URL": "https://test.com" } });
         let pm = ProfileManager::new().unwrap();
         assert!(pm.unset_value(&mut data, "env.ANTHROPIC_BASE_URL").unwrap());
-        assert!(!data["env"].get("ANTHROPIC_BASE_URL").is_some());
+        assert!(data["env"].get("ANTHROPIC_BASE_URL").is_none());
+    }
+
+    #[test]
+    fn test_validate_profile() {
+        let pm = ProfileManager::new().unwrap();
+        assert!(pm.validate_profile(&json!({ "model": "opus", "env": { "A": "b" } })).is_ok());
+        assert!(pm.validate_profile(&json!([1, 2])).is_err());
+        assert!(pm.validate_profile(&json!({ "model": 4 })).is_err());
+        assert!(pm.validate_profile(&json!({ "env": { "A": 1 } })).is_err());
+        assert!(pm.validate_profile(&json!({ "permissions": { "allow": "Bash" } })).is_err());
     }
 
     #[test]
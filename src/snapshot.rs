@@ -0,0 +1,393 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::format::Format;
+
+/// ccp's own files and directories at the top of the profiles directory.
+/// Other dotfiles (`.git`, `.DS_Store`, ...) are left out of snapshots and
+/// kept across restores, like files that are not text.
+const SIDECARS: &[&str] = &[
+    ".current",
+    ".aliases.json",
+    ".vars.json",
+    ".last-used.json",
+    ".gitignore",
+    ".meta",
+    ".history",
+    ".trash",
+];
+
+/// Everything ccp manages, in one file: the profiles directory (profiles,
+/// `.current`, aliases, vars and metadata), settings.json and ccp.json.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub created: DateTime<Local>,
+    /// settings.json as it was, if it existed
+    pub settings: Option<String>,
+    /// ccp.json as it was, if it existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefs: Option<String>,
+    /// Paths relative to the profiles directory, with `/` separators
+    pub files: BTreeMap<String, String>,
+    /// Files that had the executable bit set (script profiles)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub executable: Vec<String>,
+}
+
+/// What restoring a snapshot would change, by profile name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub current: Option<(Option<String>, Option<String>)>,
+    pub settings_changed: bool,
+}
+
+impl Snapshot {
+    pub fn capture(config: &Config) -> Result<Self> {
+        Self::capture_except(config, &[])
+    }
+
+    /// Like `capture`, but without revision history and trash. Those only
+    /// record past states, and can grow far larger than what a single
+    /// command changes, so the undo journal leaves them out.
+    pub fn capture_state(config: &Config) -> Result<Self> {
+        Self::capture_except(config, &[".history", ".trash"])
+    }
+
+    fn capture_except(config: &Config, skip: &[&str]) -> Result<Self> {
+        let mut snapshot = Snapshot {
+            created: Local::now(),
+            settings: None,
+            prefs: None,
+            files: BTreeMap::new(),
+            executable: Vec::new(),
+        };
+        snapshot.settings = read_optional(&config.settings_file)?;
+        snapshot.prefs = read_optional(&config.prefs_file)?;
+        if config.profiles_dir.exists() {
+            snapshot.collect(&config.profiles_dir, "", skip)?;
+        }
+        Ok(snapshot)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("{} is not a valid snapshot", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn collect(&mut self, dir: &Path, prefix: &str, skip: &[&str]) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let rel = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if !is_managed(&rel) || skip.contains(&rel.as_str()) {
+                continue;
+            }
+            if path.is_dir() {
+                self.collect(&path, &format!("{}/", rel), skip)?;
+                continue;
+            }
+            let Some(content) = read_text(&path)? else {
+                continue;
+            };
+            if is_executable(&path) {
+                self.executable.push(rel.clone());
+            }
+            self.files.insert(rel, content);
+        }
+        Ok(())
+    }
+
+    /// Whether both hold the same files and settings, whenever taken.
+    pub fn same_state(&self, other: &Snapshot) -> bool {
+        self.files == other.files && self.settings == other.settings && self.prefs == other.prefs
+    }
+
+    pub fn current_profile(&self) -> Option<String> {
+        self.files.get(".current").map(|c| c.trim().to_string())
+    }
+
+    /// Profile name → file content, for the profile files in the snapshot.
+    pub fn profiles(&self) -> BTreeMap<String, &str> {
+        self.files.iter()
+            .filter(|(path, _)| !path.split('/').any(|part| part.starts_with('.')))
+            .filter_map(|(path, content)| {
+                let name = match path.strip_suffix(".profile.sh") {
+                    Some(name) => name.to_string(),
+                    None => {
+                        Format::from_path(Path::new(path))?;
+                        path.rsplit_once('.').map(|(stem, _)| stem.to_string())?
+                    }
+                };
+                Some((name, content.as_str()))
+            })
+            .collect()
+    }
+
+    /// How going from `self` to `target` changes things.
+    pub fn summary(&self, target: &Snapshot) -> Summary {
+        let before = self.profiles();
+        let after = target.profiles();
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+        let mut summary = Summary::default();
+        for name in names {
+            match (before.get(name), after.get(name)) {
+                (None, Some(_)) => summary.added.push(name.clone()),
+                (Some(_), None) => summary.removed.push(name.clone()),
+                (Some(a), Some(b)) if a != b => summary.changed.push(name.clone()),
+                _ => {}
+            }
+        }
+        if self.current_profile() != target.current_profile() {
+            summary.current = Some((self.current_profile(), target.current_profile()));
+        }
+        summary.settings_changed = self.settings != target.settings;
+        summary
+    }
+
+    /// Replace the profiles directory, settings.json and ccp.json with the
+    /// snapshot's.
+    ///
+    /// Everything is written next to its target first and swapped in with
+    /// renames, so a failure part way leaves the current state in place.
+    pub fn restore(&self, config: &Config) -> Result<()> {
+        let profiles_dir = &config.profiles_dir;
+        let parent = profiles_dir.parent().context("Profiles directory has no parent")?;
+        fs::create_dir_all(parent)?;
+
+        let staging = tempfile::Builder::new()
+            .prefix(".profiles-restore-")
+            .tempdir_in(parent)
+            .context("Failed to create staging directory")?;
+        for (rel, content) in &self.files {
+            if rel.split('/').any(|part| part.is_empty() || part == "..") {
+                bail!("Snapshot contains an unsafe path '{}'", rel);
+            }
+            let path = staging.path().join(rel);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+            if self.executable.contains(rel) {
+                set_executable(&path)?;
+            }
+        }
+
+        // Top-level files are staged beside their targets and renamed in last
+        let top_level = [(&config.settings_file, &self.settings), (&config.prefs_file, &self.prefs)];
+        for (path, content) in top_level {
+            if let Some(content) = content {
+                let tmp = staged_path(path);
+                fs::write(&tmp, content).with_context(|| format!("Failed to write {}", tmp.display()))?;
+            }
+        }
+
+        // Swap directories: old → backup name, staging → profiles. Files that
+        // snapshots leave out, such as a git repository, move across as is.
+        let old = parent.join(format!(".profiles-old-{}", std::process::id()));
+        let had_profiles = profiles_dir.exists();
+        let mut kept = Vec::new();
+        if had_profiles {
+            unmanaged(profiles_dir, "", &mut kept)?;
+            kept.retain(|rel| !self.files.contains_key(rel));
+            fs::rename(profiles_dir, &old).context("Failed to move the current profiles aside")?;
+        }
+        let staged = staging.keep();
+        let mut moved = Vec::new();
+        let swapped = (|| {
+            for rel in &kept {
+                let to = staged.join(rel);
+                if let Some(dir) = to.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::rename(old.join(rel), to)?;
+                moved.push(rel);
+            }
+            fs::rename(&staged, profiles_dir)
+        })();
+        if let Err(e) = swapped {
+            for rel in &moved {
+                fs::rename(staged.join(rel), old.join(rel)).ok();
+            }
+            if had_profiles {
+                fs::rename(&old, profiles_dir).ok();
+            }
+            fs::remove_dir_all(&staged).ok();
+            return Err(e).context("Failed to move restored profiles into place");
+        }
+
+        let result = top_level.iter().try_for_each(|(path, content)| match content {
+            Some(_) => fs::rename(staged_path(path), path),
+            None if path.exists() => fs::remove_file(path),
+            None => Ok(()),
+        });
+        if let Err(e) = result {
+            // Put the old profiles back so profiles and settings stay consistent
+            for rel in &kept {
+                fs::rename(profiles_dir.join(rel), old.join(rel)).ok();
+            }
+            fs::remove_dir_all(profiles_dir).ok();
+            if had_profiles {
+                fs::rename(&old, profiles_dir).ok();
+            }
+            for (path, _) in top_level {
+                fs::remove_file(staged_path(path)).ok();
+            }
+            return Err(e).context("Failed to restore settings");
+        }
+
+        if had_profiles {
+            fs::remove_dir_all(&old).ok();
+        }
+        Ok(())
+    }
+}
+
+/// Whether a path in the profiles directory is part of snapshots: profiles,
+/// namespaces and ccp's sidecar files, but no other dotfiles.
+fn is_managed(rel: &str) -> bool {
+    let mut parts = rel.split('/');
+    let first = parts.next().unwrap_or_default();
+    (!first.starts_with('.') || SIDECARS.contains(&first)) && parts.all(|part| !part.starts_with('.'))
+}
+
+/// The content of a file, or None when it is not text.
+fn read_text(path: &Path) -> Result<Option<String>> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(String::from_utf8(bytes).ok())
+}
+
+/// Paths under `dir` that snapshots leave out, outermost only.
+fn unmanaged(dir: &Path, prefix: &str, out: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let rel = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if !is_managed(&rel) {
+            out.push(rel);
+        } else if path.is_dir() {
+            unmanaged(&path, &format!("{}/", rel), out)?;
+        } else if read_text(&path)?.is_none() {
+            out.push(rel);
+        }
+    }
+    Ok(())
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn staged_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".restore");
+    path.with_file_name(name)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(unix)]
+pub fn set_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+        .with_context(|| format!("Failed to make {} executable", path.display()))
+}
+
+#[cfg(not(unix))]
+pub fn set_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_restore_roundtrip_and_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::from_dir(dir.path());
+        fs::create_dir_all(config.profiles_dir.join("work")).unwrap();
+        fs::write(config.profiles_dir.join("a.json"), "{}").unwrap();
+        fs::write(config.profiles_dir.join("work/b.yaml"), "model: opus\n").unwrap();
+        fs::write(config.profiles_dir.join(".current"), "a").unwrap();
+        fs::write(&config.settings_file, "{}").unwrap();
+
+        let before = Snapshot::capture(&config).unwrap();
+        assert_eq!(before.profiles().keys().collect::<Vec<_>>(), ["a", "work/b"]);
+
+        fs::remove_file(config.profiles_dir.join("a.json")).unwrap();
+        fs::write(config.profiles_dir.join("work/b.yaml"), "model: haiku\n").unwrap();
+        fs::write(config.profiles_dir.join("c.json"), "{}").unwrap();
+        fs::write(config.profiles_dir.join(".current"), "c").unwrap();
+        let after = Snapshot::capture(&config).unwrap();
+
+        let summary = after.summary(&before);
+        assert_eq!(summary.added, ["a"]);
+        assert_eq!(summary.removed, ["c"]);
+        assert_eq!(summary.changed, ["work/b"]);
+        assert_eq!(summary.current, Some((Some("c".to_string()), Some("a".to_string()))));
+        assert!(!summary.settings_changed);
+
+        before.restore(&config).unwrap();
+        let restored = Snapshot::capture(&config).unwrap();
+        assert_eq!(restored.files, before.files);
+        assert!(!config.profiles_dir.join("c.json").exists());
+        assert!(restored.same_state(&before));
+        assert_eq!(restored.summary(&before), Summary::default());
+    }
+
+    #[test]
+    fn test_other_files_are_left_out_and_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::from_dir(dir.path());
+        fs::create_dir_all(config.profiles_dir.join(".git")).unwrap();
+        fs::create_dir_all(config.profiles_dir.join("work")).unwrap();
+        fs::write(config.profiles_dir.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(config.profiles_dir.join(".DS_Store"), "text, but not ccp's").unwrap();
+        fs::write(config.profiles_dir.join("work/icon.png"), [0x89, 0x50, 0xff, 0xfe]).unwrap();
+        fs::write(config.profiles_dir.join("a.json"), "{}").unwrap();
+
+        let snapshot = Snapshot::capture(&config).unwrap();
+        assert_eq!(snapshot.files.keys().collect::<Vec<_>>(), ["a.json"]);
+
+        fs::remove_file(config.profiles_dir.join("a.json")).unwrap();
+        snapshot.restore(&config).unwrap();
+        assert!(config.profiles_dir.join("a.json").exists());
+        assert!(config.profiles_dir.join(".git/HEAD").exists());
+        assert!(config.profiles_dir.join(".DS_Store").exists());
+        assert_eq!(fs::read(config.profiles_dir.join("work/icon.png")).unwrap(), [0x89, 0x50, 0xff, 0xfe]);
+    }
+}
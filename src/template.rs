@@ -0,0 +1,281 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::format::Format;
+
+/// A starting point for `ccp create --template`: fixed settings plus the
+/// fields the user has to (or may) fill in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Template {
+    /// Taken from the file name for user templates
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Settings every profile created from the template starts with
+    #[serde(default = "empty_object")]
+    pub settings: Value,
+    #[serde(default)]
+    pub fields: Vec<Field>,
+    #[serde(skip)]
+    pub builtin: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Field {
+    /// Key path in the profile, e.g. "env.AWS_REGION"
+    pub key: String,
+    /// Question asked when the value is missing
+    pub prompt: String,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Don't echo the value while typing it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+}
+
+fn empty_object() -> Value {
+    json!({})
+}
+
+impl Field {
+    /// Whether a `--var` name refers to this field: either the full key path
+    /// or its last segment (`AWS_REGION` for `env.AWS_REGION`).
+    fn matches(&self, var: &str) -> bool {
+        self.key == var || self.key.rsplit('.').next() == Some(var)
+    }
+}
+
+impl Template {
+    /// Work out the value of every field. `vars` come from the command line;
+    /// `ask` is called for required fields that have no value yet. Optional
+    /// fields without a value fall back to their default or stay unset.
+    /// Vars that match no field are passed through only when they are key
+    /// paths like `env.FOO`; any other name is taken for a mistyped field.
+    pub fn resolve<F>(&self, vars: &BTreeMap<String, String>, mut ask: F) -> Result<Vec<(String, String)>>
+    where
+        F: FnMut(&Field) -> Result<String>,
+    {
+        for var in vars.keys() {
+            if !var.contains('.') && !self.fields.iter().any(|f| f.matches(var)) {
+                bail!("{}", self.unknown_var(var));
+            }
+        }
+        
+        let mut values = Vec::new();
+        for field in &self.fields {
+            let given = vars.iter().find(|(var, _)| field.matches(var)).map(|(_, v)| v.clone());
+            let value = match given {
+                Some(value) => Some(value),
+                None if field.required => Some(ask(field)?),
+                None => field.default.clone(),
+            };
+            if let Some(value) = value {
+                if field.required && value.is_empty() {
+                    bail!("A value for '{}' is required", field.key);
+                }
+                values.push((field.key.clone(), value));
+            }
+        }
+        for (var, value) in vars {
+            if !self.fields.iter().any(|f| f.matches(var)) {
+                values.push((var.clone(), value.clone()));
+            }
+        }
+        Ok(values)
+    }
+    
+    fn unknown_var(&self, var: &str) -> String {
+        let lower = var.to_lowercase();
+        let closest = self.fields.iter()
+            .flat_map(|f| [f.key.as_str(), f.key.rsplit('.').next().unwrap_or_default()])
+            .map(|name| (strsim::levenshtein(&lower, &name.to_lowercase()), name))
+            .filter(|(distance, name)| *distance <= 2.max(name.len() / 3))
+            .min();
+        match closest {
+            Some((_, name)) => format!(
+                "Template '{}' has no field '{}'. Did you mean '{}'?",
+                self.name, var, name,
+            ),
+            None => format!(
+                "Template '{}' has no field '{}'. Use a key path like 'env.{}' to set it anyway.",
+                self.name, var, var,
+            ),
+        }
+    }
+}
+
+/// Templates shipped with ccp.
+pub fn builtin() -> Vec<Template> {
+    let model = || json!({ "key": "model", "prompt": "Model", "required": false });
+    let templates = [
+        ("anthropic", json!({
+            "description": "Anthropic API with an API key",
+            "settings": {},
+            "fields": [
+                { "key": "env.ANTHROPIC_API_KEY", "prompt": "Anthropic API key", "required": true, "secret": true },
+                model(),
+            ],
+        })),
+        ("bedrock", json!({
+            "description": "Claude on Amazon Bedrock",
+            "settings": { "env": { "CLAUDE_CODE_USE_BEDROCK": "1" } },
+            "fields": [
+                { "key": "env.AWS_REGION", "prompt": "AWS region", "required": true, "default": "us-east-1" },
+                { "key": "env.AWS_PROFILE", "prompt": "AWS profile", "required": false },
+                model(),
+            ],
+        })),
+        ("vertex", json!({
+            "description": "Claude on Google Vertex AI",
+            "settings": { "env": { "CLAUDE_CODE_USE_VERTEX": "1" } },
+            "fields": [
+                { "key": "env.CLOUD_ML_REGION", "prompt": "Vertex region", "required": true, "default": "us-east5" },
+                { "key": "env.ANTHROPIC_VERTEX_PROJECT_ID", "prompt": "GCP project ID", "required": true },
+                model(),
+            ],
+        })),
+        ("zai", json!({
+            "description": "z.ai (GLM) Anthropic-compatible API",
+            "settings": { "env": { "ANTHROPIC_BASE_URL": "https://api.z.ai/api/anthropic" } },
+            "fields": [
+                { "key": "env.ANTHROPIC_AUTH_TOKEN", "prompt": "z.ai API key", "required": true, "secret": true },
+                model(),
+            ],
+        })),
+        ("minimax", json!({
+            "description": "MiniMax Anthropic-compatible API",
+            "settings": { "env": { "ANTHROPIC_BASE_URL": "https://api.minimax.io/anthropic" } },
+            "fields": [
+                { "key": "env.ANTHROPIC_AUTH_TOKEN", "prompt": "MiniMax API key", "required": true, "secret": true },
+                model(),
+            ],
+        })),
+        ("openrouter", json!({
+            "description": "OpenRouter",
+            "settings": { "env": { "ANTHROPIC_BASE_URL": "https://openrouter.ai/api", "ANTHROPIC_API_KEY": "" } },
+            "fields": [
+                { "key": "env.ANTHROPIC_AUTH_TOKEN", "prompt": "OpenRouter API key", "required": true, "secret": true },
+                model(),
+            ],
+        })),
+        ("litellm", json!({
+            "description": "Local LiteLLM proxy",
+            "settings": {},
+            "fields": [
+                { "key": "env.ANTHROPIC_BASE_URL", "prompt": "Proxy URL", "required": true, "default": "http://localhost:4000" },
+                { "key": "env.ANTHROPIC_AUTH_TOKEN", "prompt": "Proxy key", "required": false, "secret": true },
+                model(),
+            ],
+        })),
+    ];
+
+    templates.into_iter()
+        .map(|(name, data)| {
+            let mut template: Template = serde_json::from_value(data).expect("built-in template is valid");
+            template.name = name.to_string();
+            template.builtin = true;
+            template
+        })
+        .collect()
+}
+
+/// User templates from `dir`, one JSON, YAML or TOML file each.
+pub fn load_user(dir: &Path) -> Result<Vec<Template>> {
+    let mut templates = Vec::new();
+    if !dir.exists() {
+        return Ok(templates);
+    }
+
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.path());
+
+    for entry in entries {
+        let path = entry.path();
+        let (Some(format), Some(stem)) = (Format::from_path(&path), path.file_stem()) else {
+            continue;
+        };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut template: Template = serde_json::from_value(format.parse(&text)?)
+            .with_context(|| format!("Invalid template {}", path.display()))?;
+        template.name = stem.to_string_lossy().to_string();
+        templates.push(template);
+    }
+    Ok(templates)
+}
+
+/// Built-in and user templates; a user template replaces a built-in one of
+/// the same name.
+pub fn all(dir: &Path) -> Result<Vec<Template>> {
+    let user = load_user(dir)?;
+    let mut templates: Vec<Template> = builtin().into_iter()
+        .filter(|b| !user.iter().any(|u| u.name == b.name))
+        .collect();
+    templates.extend(user);
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+pub fn find(dir: &Path, name: &str) -> Result<Template> {
+    let templates = all(dir)?;
+    if let Some(template) = templates.iter().find(|t| t.name == name) {
+        return Ok(template.clone());
+    }
+    let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+    bail!("Template '{}' does not exist. Available templates: {}", name, names.join(", "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates() {
+        let names: Vec<String> = builtin().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, ["anthropic", "bedrock", "vertex", "zai", "minimax", "openrouter", "litellm"]);
+    }
+
+    #[test]
+    fn test_resolve_asks_only_for_missing_required_fields() {
+        let template = builtin().into_iter().find(|t| t.name == "vertex").unwrap();
+        let vars = BTreeMap::from([
+            ("CLOUD_ML_REGION".to_string(), "europe-west1".to_string()),
+            ("env.DISABLE_TELEMETRY".to_string(), "1".to_string()),
+        ]);
+        let mut asked = Vec::new();
+        let values = template.resolve(&vars, |field| {
+            asked.push(field.key.clone());
+            Ok("my-project".to_string())
+        }).unwrap();
+
+        assert_eq!(asked, ["env.ANTHROPIC_VERTEX_PROJECT_ID"]);
+        assert_eq!(values, [
+            ("env.CLOUD_ML_REGION".to_string(), "europe-west1".to_string()),
+            ("env.ANTHROPIC_VERTEX_PROJECT_ID".to_string(), "my-project".to_string()),
+            ("env.DISABLE_TELEMETRY".to_string(), "1".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_resolve_rejects_unknown_field_names() {
+        let template = builtin().into_iter().find(|t| t.name == "bedrock").unwrap();
+        let resolve = |var: &str| {
+            let vars = BTreeMap::from([(var.to_string(), "x".to_string())]);
+            template.resolve(&vars, |_| Ok("x".to_string())).unwrap_err().to_string()
+        };
+        assert_eq!(resolve("modle"), "Template 'bedrock' has no field 'modle'. Did you mean 'model'?");
+        assert_eq!(resolve("AWS_REGON"), "Template 'bedrock' has no field 'AWS_REGON'. Did you mean 'AWS_REGION'?");
+        assert!(resolve("FOO").contains("'env.FOO'"));
+    }
+}
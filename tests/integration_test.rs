@@ -1,3 +1,7 @@
+// `Command::cargo_bin` is deprecated in newer assert_cmd releases in favour
+// of the `cargo_bin_cmd!` macro; the tests keep using it until they move over.
+#![allow(deprecated)]
+
 use assert_cmd::Command;
 use assert_fs::prelude::*;
 use assert_fs::TempDir;
@@ -346,3 +350,1399 @@ fn test_cli_unset() {
     let data: serde_json::Value = serde_json::from_str(&content).unwrap();
     assert!(data.get("custom").is_none());
 }
+
+#[test]
+fn test_cli_edit_applies_to_current() {
+    let (home_dir, _) = create_test_home();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .env_remove("VISUAL")
+        .env("EDITOR", "sed -i s/sonnet-4/opus-4/")
+        .arg("edit")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let profile = std::fs::read_to_string(home_dir.path().join(".claude/profiles/default.json")).unwrap();
+    let data: serde_json::Value = serde_json::from_str(&profile).unwrap();
+    assert_eq!(data["model"], "opus-4");
+
+    let settings = std::fs::read_to_string(home_dir.path().join(".claude/settings.json")).unwrap();
+    let data: serde_json::Value = serde_json::from_str(&settings).unwrap();
+    assert_eq!(data["model"], "opus-4");
+}
+
+#[test]
+fn test_cli_edit_rejects_invalid_json() {
+    let (home_dir, _) = create_test_home();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .env_remove("VISUAL")
+        .env("EDITOR", "sed -i s/sonnet-4\"/sonnet-4/")
+        .arg("edit")
+        .arg("default")
+        .output()
+        .unwrap();
+
+    // No terminal to answer the retry prompt, so the edit must be abandoned
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid JSON"));
+
+    let profile = std::fs::read_to_string(home_dir.path().join(".claude/profiles/default.json")).unwrap();
+    assert_eq!(profile, r#"{"model": "sonnet-4"}"#);
+}
+
+#[test]
+fn test_cli_set_preserves_order_and_formatting() {
+    let (home_dir, _) = create_test_home();
+
+    let profile_path = home_dir.path().join(".claude/profiles/default.json");
+    let original = "{\n    \"model\": \"sonnet-4\",\n    \"env\": {\n        \"ZED\": \"1\",\n        \"ALPHA\": \"2\"\n    },\n    \"alwaysThinkingEnabled\": false\n}\n";
+    std::fs::write(&profile_path, original).unwrap();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .arg("set")
+        .arg("env.MIDDLE")
+        .arg("3")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let content = std::fs::read_to_string(&profile_path).unwrap();
+    assert_eq!(content, original.replace("\"ALPHA\": \"2\"\n", "\"ALPHA\": \"2\",\n        \"MIDDLE\": 3\n"));
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .arg("unset")
+        .arg("env.MIDDLE")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let content = std::fs::read_to_string(&profile_path).unwrap();
+    assert_eq!(content, original);
+}
+
+#[test]
+fn test_cli_jsonc_profile() {
+    let (home_dir, _) = create_test_home();
+
+    let profile_path = home_dir.path().join(".claude/profiles/commented.json");
+    let original = "{\n  // Pinned until the new model is validated\n  \"model\": \"sonnet-4\",\n  \"permissions\": {\n    \"allow\": [\"Bash(git:*)\"], // needed for releases\n  },\n}\n";
+    std::fs::write(&profile_path, original).unwrap();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .arg("set")
+        .arg("model")
+        .arg("opus-4")
+        .arg("-p")
+        .arg("commented")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let content = std::fs::read_to_string(&profile_path).unwrap();
+    assert_eq!(content, original.replace("sonnet-4", "opus-4"));
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .arg("use")
+        .arg("commented")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // settings.json must be strict JSON
+    let settings = std::fs::read_to_string(home_dir.path().join(".claude/settings.json")).unwrap();
+    let data: serde_json::Value = serde_json::from_str(&settings).unwrap();
+    assert_eq!(data["model"], "opus-4");
+    assert_eq!(data["permissions"]["allow"][0], "Bash(git:*)");
+}
+
+#[test]
+fn test_cli_yaml_profile() {
+    let (home_dir, _) = create_test_home();
+
+    let profile_path = home_dir.path().join(".claude/profiles/team.yaml");
+    std::fs::write(&profile_path, "model: sonnet-4\nenv:\n  AWS_REGION: us-east-1\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd.env("HOME", home_dir.path()).arg("list").output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("team"));
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .args(["set", "model", "opus-4", "-p", "team"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    // Saved back as YAML
+    let content = std::fs::read_to_string(&profile_path).unwrap();
+    assert!(content.contains("model: opus-4"));
+    assert!(!home_dir.path().join(".claude/profiles/team.json").exists());
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd.env("HOME", home_dir.path()).args(["use", "team"]).output().unwrap();
+    assert!(output.status.success());
+
+    let settings = std::fs::read_to_string(home_dir.path().join(".claude/settings.json")).unwrap();
+    let data: serde_json::Value = serde_json::from_str(&settings).unwrap();
+    assert_eq!(data["model"], "opus-4");
+    assert_eq!(data["env"]["AWS_REGION"], "us-east-1");
+}
+
+#[test]
+fn test_cli_format_conflict() {
+    let (home_dir, _) = create_test_home();
+
+    std::fs::write(home_dir.path().join(".claude/profiles/default.toml"), "model = \"haiku\"\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd.env("HOME", home_dir.path()).args(["use", "default"]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("more than one format"));
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd.env("HOME", home_dir.path()).arg("list").output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("conflicting files"));
+}
+
+#[test]
+fn test_cli_export_import_format() {
+    let (home_dir, _) = create_test_home();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .args(["export", "default", "--format", "toml"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert_eq!(stdout.trim(), "model = \"sonnet-4\"");
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .args(["import", "from-toml", "--format", "toml"])
+        .write_stdin(stdout)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(home_dir.path().join(".claude/profiles/from-toml.toml").exists());
+}
+
+#[test]
+fn test_cli_rejects_unsafe_names() {
+    let (home_dir, _) = create_test_home();
+
+    let cases: [&[&str]; 5] = [
+        &["create", "../evil"],
+        &["create", "my profile"],
+        &["copy", "default", ".current"],
+        &["backup", "../../outside"],
+        &["restore", "../settings"],
+    ];
+
+    for args in cases {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(!output.status.success(), "{:?} should fail", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("name"), "{:?}: {}", args, stderr);
+    }
+
+    assert!(!home_dir.path().join(".claude/evil.json").exists());
+    assert!(!home_dir.path().join("outside.json").exists());
+    let current = std::fs::read_to_string(home_dir.path().join(".claude/profiles/.current")).unwrap();
+    assert_eq!(current, "default");
+}
+
+#[test]
+fn test_cli_import_rejects_unsafe_name() {
+    let (home_dir, _) = create_test_home();
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    let output = cmd
+        .env("HOME", home_dir.path())
+        .args(["import", r"..\imported"])
+        .write_stdin(r#"{"model": "opus-4"}"#)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot start with '.'"));
+    assert!(std::fs::read_dir(home_dir.path().join(".claude/profiles")).unwrap().count() == 2);
+}
+
+#[test]
+fn test_cli_namespaced_profiles() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    run(&["create", "work/client-a", "--from", "default"]);
+    assert!(profiles_dir.join("work/client-a.json").exists());
+
+    run(&["copy", "work/client-a", "work/eu/client-b"]);
+    run(&["rename", "default", "personal/main"]);
+    assert!(profiles_dir.join("personal/main.json").exists());
+
+    let stdout = run(&["list"]);
+    let lines: Vec<&str> = stdout.lines().map(|l| l.trim_end()).collect();
+    assert_eq!(lines[1..], [
+        "    personal/",
+        "  →   main",
+        "    work/",
+        "      client-a",
+        "      eu/",
+        "        client-b",
+    ]);
+
+    let stdout = run(&["list", "work/eu/"]);
+    assert!(stdout.contains("client-b"));
+    assert!(!stdout.contains("client-a"));
+
+    // Moving the last profile out of a namespace removes its directory
+    run(&["rename", "work/eu/client-b", "client-b"]);
+    assert!(!profiles_dir.join("work/eu").exists());
+
+    run(&["use", "work/client-a"]);
+    run(&["delete", "work/client-a", "--force"]);
+    assert!(!profiles_dir.join("work").exists());
+}
+
+#[test]
+fn test_cli_alias_and_name_resolution() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    std::fs::write(profiles_dir.join("anthropic-prod-sonnet.json"), r#"{"model": "sonnet"}"#).unwrap();
+    std::fs::write(profiles_dir.join("anthropic-dev.json"), r#"{"model": "haiku"}"#).unwrap();
+    std::fs::write(profiles_dir.join("Bedrock.json"), r#"{"model": "opus"}"#).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).args(args).output().unwrap()
+    };
+    let current = || std::fs::read_to_string(profiles_dir.join(".current")).unwrap();
+
+    assert!(run(&["alias", "set", "p", "anthropic-prod-sonnet"]).status.success());
+    assert!(run(&["use", "p"]).status.success());
+    assert_eq!(current(), "anthropic-prod-sonnet");
+
+    // Unique prefix and case-insensitive match
+    assert!(run(&["use", "anthropic-d"]).status.success());
+    assert_eq!(current(), "anthropic-dev");
+    assert!(run(&["use", "bedrock"]).status.success());
+    assert_eq!(current(), "Bedrock");
+
+    let output = run(&["use", "anthropic"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("matches several profiles"));
+
+    let output = run(&["use", "anthropic-prd-sonnet"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Did you mean 'anthropic-prod-sonnet'"));
+
+    // Deleting, renaming and reverting need the exact name or an alias
+    let output = run(&["delete", "anthropic-d", "--force"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Did you mean 'anthropic-dev'?"));
+    assert!(profiles_dir.join("anthropic-dev.json").exists());
+    assert!(!run(&["rename", "bedrock", "aws"]).status.success());
+    assert!(!run(&["revert", "anthropic-d", "1"]).status.success());
+
+    // Renaming a profile keeps its aliases pointing at it
+    assert!(run(&["rename", "p", "prod"]).status.success());
+    let aliases = std::fs::read_to_string(profiles_dir.join(".aliases.json")).unwrap();
+    let aliases: serde_json::Value = serde_json::from_str(&aliases).unwrap();
+    assert_eq!(aliases["p"], "prod");
+
+    let output = run(&["alias", "set", "default", "prod"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_profile_metadata() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    std::fs::write(profiles_dir.join("work.json"), r#"{"model": "opus"}"#).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "ccp {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = run(&["describe", "work", "Client work on Opus", "--owner", "alice"]);
+    assert!(stdout.contains("Updated description and owner of 'work'"));
+    assert!(run(&["describe", "work", "--owner", "alice"]).contains("Updated owner of 'work'"));
+    run(&["tag", "add", "work", "client", "opus", "client"]);
+    run(&["use", "work"]);
+
+    let stdout = run(&["describe", "work"]);
+    assert!(stdout.contains("Client work on Opus"));
+    assert!(stdout.contains("client, opus"));
+    assert!(stdout.contains("alice"));
+
+    let stdout = run(&["list", "--long"]);
+    assert!(stdout.contains("Client work on Opus"));
+    assert!(stdout.contains("[client, opus]"));
+    assert!(stdout.contains("used just now"));
+
+    // The profile file itself is untouched by metadata
+    let profile = std::fs::read_to_string(profiles_dir.join("work.json")).unwrap();
+    assert_eq!(profile, r#"{"model": "opus"}"#);
+
+    // Copies keep description and tags; renames keep everything
+    run(&["copy", "work", "work2"]);
+    run(&["rename", "work2", "work3"]);
+    let stdout = run(&["describe", "work3"]);
+    assert!(stdout.contains("Client work on Opus"));
+    assert!(!profiles_dir.join(".meta/work2.json").exists());
+
+    run(&["tag", "remove", "work3", "opus"]);
+    let stdout = run(&["tag", "list"]);
+    assert!(stdout.contains("client") && stdout.contains("(2)"));
+    assert!(stdout.contains("opus") && stdout.contains("(1)"));
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    cmd.env("HOME", home_dir.path())
+        .args(["tag", "add", "work", "two words"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_tag_filters_and_bulk_set() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    std::fs::write(profiles_dir.join("a.json"), r#"{"model": "sonnet"}"#).unwrap();
+    std::fs::write(profiles_dir.join("b.json"), r#"{"model": "opus"}"#).unwrap();
+    std::fs::write(profiles_dir.join("c.json"), r#"{"model": "sonnet"}"#).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "ccp {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let model = |name: &str| {
+        let text = std::fs::read_to_string(profiles_dir.join(format!("{}.json", name))).unwrap();
+        serde_json::from_str::<serde_json::Value>(&text).unwrap()["model"].clone()
+    };
+
+    run(&["tag", "add", "a", "team"]);
+    run(&["tag", "add", "b", "team"]);
+
+    let stdout = run(&["list", "--tag", "team"]);
+    assert!(stdout.contains("a") && stdout.contains("b"));
+    assert!(!stdout.contains(" c"));
+
+    let stdout = run(&["set", "model", "opus", "--tag", "team", "--yes"]);
+    assert!(stdout.contains(r#"model: "sonnet" → "opus""#));
+    assert!(stdout.contains("unchanged"));
+    assert!(stdout.contains("Updated 1 profile(s)"));
+    assert_eq!(model("a"), "opus");
+    assert_eq!(model("c"), "sonnet");
+
+    let bundle = run(&["export", "--tag", "team"]);
+    let parsed: serde_json::Value = serde_json::from_str(&bundle).unwrap();
+    assert_eq!(parsed["profiles"]["b"]["model"], "opus");
+
+    run(&["unset", "model", "-t", "team", "-y"]);
+    assert!(model("a").is_null() && model("b").is_null());
+
+    // A bundle imports into a fresh home
+    let (other_home, _) = create_test_home();
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    cmd.env("HOME", other_home.path()).arg("import").write_stdin(bundle).assert().success();
+    assert!(other_home.path().join(".claude/profiles/a.json").exists());
+    assert!(other_home.path().join(".claude/profiles/b.json").exists());
+}
+
+#[test]
+fn test_cli_locked_profile() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    std::fs::write(profiles_dir.join("prod.json"), r#"{"model": "opus"}"#).unwrap();
+
+    let ccp = || {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path());
+        cmd
+    };
+
+    ccp().args(["snapshot", "create", "unlocked"]).assert().success();
+    ccp().args(["lock", "prod"]).assert().success();
+    let output = ccp().arg("list").output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("prod 🔒"));
+
+    for args in [
+        vec!["set", "model", "sonnet", "-p", "prod"],
+        vec!["unset", "model", "-p", "prod"],
+        vec!["rename", "prod", "production"],
+        vec!["delete", "prod"],
+        vec!["edit", "prod"],
+        vec!["configure", "prod"],
+        vec!["describe", "prod", "Production"],
+        vec!["tag", "add", "prod", "live"],
+        vec!["notes", "edit", "prod"],
+        vec!["archive", "prod"],
+        vec!["snapshot", "restore", "unlocked", "--yes"],
+    ] {
+        let output = ccp().args(&args).output().unwrap();
+        assert!(!output.status.success(), "ccp {:?} should be refused", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("is locked"));
+    }
+
+    let output = ccp().args(["import", "prod"]).write_stdin(r#"{"model": "haiku"}"#).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is locked"));
+    assert!(std::fs::read_to_string(profiles_dir.join("prod.json")).unwrap().contains("opus"));
+
+    ccp().args(["set", "model", "sonnet", "-p", "prod", "--force"]).assert().success();
+    assert!(std::fs::read_to_string(profiles_dir.join("prod.json")).unwrap().contains("sonnet"));
+
+    ccp().args(["import", "prod", "--force"]).write_stdin(r#"{"model": "haiku"}"#).assert().success();
+    assert!(std::fs::read_to_string(profiles_dir.join("prod.json")).unwrap().contains("haiku"));
+
+    // Undo is a change like any other
+    let output = ccp().arg("undo").output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is locked"));
+    assert!(std::fs::read_to_string(profiles_dir.join("prod.json")).unwrap().contains("haiku"));
+    ccp().args(["describe", "prod", "Production", "--force"]).assert().success();
+    // Switching to a locked profile records its use
+    ccp().args(["use", "prod"]).assert().success();
+
+    // The lock survives --force and copies start unlocked
+    ccp().args(["copy", "prod", "staging"]).assert().success();
+    ccp().args(["set", "model", "opus", "-p", "staging"]).assert().success();
+    ccp().args(["unset", "model", "-p", "prod"]).assert().failure();
+
+    ccp().args(["unlock", "prod"]).assert().success();
+    ccp().args(["unset", "model", "-p", "prod"]).assert().success();
+}
+
+#[test]
+fn test_cli_create_from_template() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+
+    let ccp = || {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path());
+        cmd
+    };
+    let profile = |name: &str| {
+        let text = std::fs::read_to_string(profiles_dir.join(format!("{}.json", name))).unwrap();
+        serde_json::from_str::<serde_json::Value>(&text).unwrap()
+    };
+
+    // Required fields with a default don't need a value without a terminal
+    ccp().args(["create", "aws", "--template", "bedrock", "--var", "AWS_PROFILE=work"]).assert().success();
+    let data = profile("aws");
+    assert_eq!(data["env"]["CLAUDE_CODE_USE_BEDROCK"], "1");
+    assert_eq!(data["env"]["AWS_REGION"], "us-east-1");
+    assert_eq!(data["env"]["AWS_PROFILE"], "work");
+
+    let output = ccp().args(["create", "gcp", "--template", "vertex"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--var ANTHROPIC_VERTEX_PROJECT_ID="));
+
+    ccp().args(["create", "gcp", "-t", "vertex", "--var", "ANTHROPIC_VERTEX_PROJECT_ID=p1", "--var", "model=opus"])
+        .assert()
+        .success();
+    assert_eq!(profile("gcp")["env"]["ANTHROPIC_VERTEX_PROJECT_ID"], "p1");
+    assert_eq!(profile("gcp")["model"], "opus");
+
+    // User templates live in their own directory and show up in the list
+    let templates_dir = home_dir.path().join(".claude/profile-templates");
+    std::fs::create_dir_all(&templates_dir).unwrap();
+    std::fs::write(templates_dir.join("corp.yaml"), "\
+description: Corporate gateway
+settings:
+  env:
+    ANTHROPIC_BASE_URL: https://llm.corp.example
+fields:
+  - key: env.ANTHROPIC_AUTH_TOKEN
+    prompt: Gateway token
+    required: true
+").unwrap();
+
+    let output = ccp().args(["template", "list"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("openrouter"));
+    assert!(stdout.contains("Corporate gateway"));
+
+    ccp().args(["create", "corp", "-t", "corp", "--var", "ANTHROPIC_AUTH_TOKEN=t"]).assert().success();
+    assert_eq!(profile("corp")["env"]["ANTHROPIC_BASE_URL"], "https://llm.corp.example");
+
+    ccp().args(["template", "show", "nope"]).assert().failure();
+}
+
+#[test]
+fn test_cli_variable_interpolation() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let profiles_dir = claude_dir.join("profiles");
+    std::fs::write(profiles_dir.join("work.json"), r#"{
+  "vars": { "ORG": "acme" },
+  "env": {
+    "ANTHROPIC_BASE_URL": "https://${PROXY_HOST}/${ORG}",
+    "HTTP_PROXY": "${HTTP_PROXY:-}",
+    "USER_HOME": "${CCP_TEST_HOME}"
+  }
+}"#).unwrap();
+    std::fs::write(profiles_dir.join(".vars.json"), r#"{ "PROXY_HOST": "proxy.example", "ORG": "global" }"#).unwrap();
+
+    let ccp = || {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).env("CCP_TEST_HOME", "/home/test").env_remove("HTTP_PROXY");
+        cmd
+    };
+
+    ccp().args(["use", "work"]).assert().success();
+    let settings: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(claude_dir.join("settings.json")).unwrap()).unwrap();
+    assert_eq!(settings["env"]["ANTHROPIC_BASE_URL"], "https://proxy.example/acme");
+    assert_eq!(settings["env"]["HTTP_PROXY"], "");
+    assert_eq!(settings["env"]["USER_HOME"], "/home/test");
+    assert!(settings.get("vars").is_none());
+
+    let output = ccp().args(["export", "work"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("https://${PROXY_HOST}/${ORG}"));
+    let output = ccp().args(["export", "work", "--resolved"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("https://proxy.example/acme"));
+    assert!(!stdout.contains("\"vars\""));
+
+    let output = ccp().args(["set", "model", "${MISSING_MODEL}", "-p", "work"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Undefined variable 'MISSING_MODEL' in 'model'"));
+    assert!(!std::fs::read_to_string(profiles_dir.join("work.json")).unwrap().contains("MISSING_MODEL"));
+}
+
+#[test]
+fn test_cli_when_blocks_and_explain() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    std::fs::write(claude_dir.join("profiles/work.json"), r#"{
+  "env": { "HTTPS_PROXY": "", "REGION": "us" },
+  "when": [
+    { "env": "CCP_TEST_OFFICE", "settings": { "env": { "HTTPS_PROXY": "http://proxy:3128" } } },
+    { "user": "ccp-test-*", "settings": { "env": { "REGION": "${CCP_TEST_REGION}" } } },
+    { "user": "nobody", "settings": { "env": { "REGION": "eu" } } }
+  ]
+}"#).unwrap();
+
+    let ccp = |office: bool| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).env("USER", "ccp-test-user").env("CCP_TEST_REGION", "ap");
+        if office {
+            cmd.env("CCP_TEST_OFFICE", "1");
+        } else {
+            cmd.env_remove("CCP_TEST_OFFICE");
+        }
+        cmd
+    };
+    let settings = || -> serde_json::Value {
+        serde_json::from_str(&std::fs::read_to_string(claude_dir.join("settings.json")).unwrap()).unwrap()
+    };
+
+    ccp(true).args(["use", "work"]).assert().success();
+    assert_eq!(settings()["env"]["HTTPS_PROXY"], "http://proxy:3128");
+    assert_eq!(settings()["env"]["REGION"], "ap");
+    assert!(settings().get("when").is_none());
+
+    ccp(false).args(["use", "work"]).assert().success();
+    assert_eq!(settings()["env"]["HTTPS_PROXY"], "");
+
+    let output = ccp(true).args(["explain", "env"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#"env.HTTPS_PROXY = "http://proxy:3128""#));
+    assert!(stdout.contains("from when[0] (env=CCP_TEST_OFFICE)"));
+    assert!(stdout.contains("overrides profile"));
+    assert!(stdout.contains("from when[1] (user=ccp-test-*)"));
+    assert!(stdout.contains(r#"expanded from "${CCP_TEST_REGION}""#));
+    assert!(stdout.contains("when[2] (user=nobody) does not match this machine"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_generated_profiles() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let profiles_dir = claude_dir.join("profiles");
+
+    let script = profiles_dir.join("minted.profile.sh");
+    std::fs::write(&script, "#!/bin/sh\necho '{\"env\": {\"ANTHROPIC_AUTH_TOKEN\": \"tok-'\"$CCP_TEST_TOKEN\"'\"}}'\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    std::fs::write(profiles_dir.join("dated.json"), r#"{
+  "model": "sonnet",
+  "env": { "STATIC": "1" },
+  "generator": "printf '{\"model\": \"opus\", \"env\": {\"DYNAMIC\": \"2\"}}'"
+}"#).unwrap();
+    std::fs::write(profiles_dir.join("broken.json"), r#"{ "generator": "echo oops >&2; exit 3" }"#).unwrap();
+    std::fs::write(profiles_dir.join("slow.json"), r#"{ "generator": { "command": "(sleep 2; touch late) & sleep 5", "timeout": 1 } }"#).unwrap();
+
+    let ccp = || {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).env("CCP_TEST_TOKEN", "abc");
+        cmd
+    };
+    let settings = || -> serde_json::Value {
+        serde_json::from_str(&std::fs::read_to_string(claude_dir.join("settings.json")).unwrap()).unwrap()
+    };
+
+    let output = ccp().arg("list").output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("minted"));
+
+    ccp().args(["use", "minted"]).assert().success();
+    assert_eq!(settings()["env"]["ANTHROPIC_AUTH_TOKEN"], "tok-abc");
+
+    ccp().args(["use", "dated"]).assert().success();
+    assert_eq!(settings()["model"], "opus");
+    assert_eq!(settings()["env"], serde_json::json!({ "STATIC": "1", "DYNAMIC": "2" }));
+    assert!(settings().get("generator").is_none());
+
+    let output = ccp().args(["export", "dated", "--resolved"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"DYNAMIC\": \"2\""));
+    let output = ccp().args(["export", "dated"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"generator\""));
+
+    let output = ccp().args(["use", "broken"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("oops"));
+
+    let output = ccp().args(["use", "slow"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("timed out after 1s"));
+    assert_eq!(settings()["model"], "opus");
+    // The timeout kills the whole process group, not just the shell
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert!(!profiles_dir.join("late").exists());
+
+    // Importing a generator needs --force
+    let output = ccp().args(["import", "shared"]).write_stdin(r#"{"generator": "echo {}"}"#).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Use --force if you trust it"));
+    assert!(!profiles_dir.join("shared.json").exists());
+    let output = ccp().args(["import"]).write_stdin(r#"{"profiles": {"a": {}, "b": {"generator": "echo {}"}}}"#).output().unwrap();
+    assert!(!output.status.success());
+    assert!(!profiles_dir.join("a.json").exists());
+    ccp().args(["import", "shared", "--force"]).write_stdin(r#"{"generator": "echo {}"}"#).assert().success();
+    assert!(profiles_dir.join("shared.json").exists());
+
+    // Script profiles are changed by editing the script
+    let output = ccp().args(["set", "model", "haiku", "-p", "minted"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("change the script instead"));
+    ccp().args(["rename", "minted", "tokens/minted"]).assert().success();
+    assert!(profiles_dir.join("tokens/minted.profile.sh").exists());
+}
+
+#[test]
+fn test_cli_generate_matrix() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    std::fs::write(profiles_dir.join("anthropic.json"), r#"{"model": "sonnet", "env": {"A": "1"}}"#).unwrap();
+    std::fs::write(profiles_dir.join("anthropic-haiku-eu.example.com.json"), r#"{"model": "old"}"#).unwrap();
+    let urls = home_dir.path().join("urls.txt");
+    std::fs::write(&urls, "# proxies\nhttps://us.example.com\n\nhttps://eu.example.com\n").unwrap();
+    let vary_urls = format!("env.ANTHROPIC_BASE_URL=@{}", urls.display());
+
+    let ccp = |extra: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path())
+            .args(["generate", "--base", "anthropic", "--vary", "model=opus,haiku", "--vary", &vary_urls])
+            .args(extra);
+        let output = cmd.output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = ccp(&["--dry-run"]);
+    assert!(stdout.contains("anthropic-opus-us.example.com"));
+    assert!(stdout.contains("Would create 3, overwrite 0, skipped 1"));
+    assert!(!profiles_dir.join("anthropic-opus-us.example.com.json").exists());
+
+    let stdout = ccp(&[]);
+    assert!(stdout.contains("exists, skipped"));
+    let text = std::fs::read_to_string(profiles_dir.join("anthropic-opus-us.example.com.json")).unwrap();
+    let data: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(data, serde_json::json!({
+        "model": "opus",
+        "env": { "A": "1", "ANTHROPIC_BASE_URL": "https://us.example.com" }
+    }));
+
+    let stdout = ccp(&["--overwrite", "--name", "bench/{model}-{ANTHROPIC_BASE_URL}"]);
+    assert!(stdout.contains("Created 4, overwrote 0, skipped 0"));
+    assert!(profiles_dir.join("bench/haiku-eu.example.com.json").exists());
+
+    // Overwriting the current profile re-applies it
+    std::fs::write(profiles_dir.join(".current"), "anthropic-haiku-eu.example.com").unwrap();
+    let stdout = ccp(&["--overwrite"]);
+    assert!(stdout.contains("Created 0, overwrote 4, skipped 0"));
+    assert!(stdout.contains("settings.json was updated too"));
+    let settings = std::fs::read_to_string(home_dir.path().join(".claude/settings.json")).unwrap();
+    assert!(settings.contains("https://eu.example.com"));
+
+    let mut cmd = Command::cargo_bin("ccp").unwrap();
+    cmd.env("HOME", home_dir.path())
+        .args(["generate", "--base", "anthropic", "--vary", "model=opus,haiku", "--name", "fixed"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_backup_prune_and_retention() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let backups_dir = claude_dir.join("backups");
+    std::fs::create_dir_all(&backups_dir).unwrap();
+    std::fs::write(claude_dir.join("settings.json"), r#"{"model": "opus"}"#).unwrap();
+
+    let now = std::time::SystemTime::now();
+    let day = std::time::Duration::from_secs(86400);
+    for (name, days) in [
+        ("backup-20240101-000000", 90),
+        ("backup-20240201-000000", 60),
+        ("pre-restore-20240301-000000", 40),
+        ("pre-restore-20240401-000000", 10),
+        ("before-upgrade", 100),
+    ] {
+        let path = backups_dir.join(format!("{}.json", name));
+        std::fs::write(&path, "{}").unwrap();
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(now - day * days).unwrap();
+    }
+    let exists = |name: &str| backups_dir.join(format!("{}.json", name)).exists();
+
+    let ccp = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = ccp(&["backup", "prune", "--older-than", "30d", "--dry-run"]);
+    assert!(stdout.contains("Would delete 3 backup(s)"));
+    assert!(!stdout.contains("before-upgrade"));
+    assert!(exists("backup-20240101-000000"));
+
+    ccp(&["backup", "prune", "--older-than", "30d", "--prefix", "pre-restore-"]);
+    assert!(!exists("pre-restore-20240301-000000"));
+    assert!(exists("pre-restore-20240401-000000"));
+    assert!(exists("backup-20240101-000000"));
+
+    // Retention runs after each backup and leaves named backups alone
+    ccp(&["backup", "retention", "--keep", "2"]);
+    ccp(&["backup"]);
+    assert!(!exists("backup-20240101-000000"));
+    assert!(!exists("backup-20240201-000000"));
+    assert!(exists("pre-restore-20240401-000000"));
+    assert!(exists("before-upgrade"));
+
+    // The backup being restored survives the pruning after the pre-restore backup
+    let stdout = ccp(&["restore", "pre-restore-20240401-000000"]);
+    assert!(stdout.contains("Restored from 'pre-restore-20240401-000000'"));
+    assert!(exists("pre-restore-20240401-000000"));
+    assert_eq!(std::fs::read_to_string(claude_dir.join("settings.json")).unwrap(), "{}");
+
+    ccp(&["backup", "prune", "--keep", "0", "--include-named"]);
+    assert!(!exists("before-upgrade"));
+
+    ccp(&["backup", "retention", "--off"]);
+    ccp(&["backup", "prune-me"]);
+    assert!(exists("prune-me"));
+}
+
+#[test]
+fn test_cli_backup_list_show_delete() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    std::fs::write(claude_dir.join("profiles/work.json"), r#"{"model": "opus"}"#).unwrap();
+    std::fs::write(claude_dir.join("settings.json"), r#"{
+  "model": "opus",
+  "env": { "ANTHROPIC_AUTH_TOKEN": "sk-ant-1234567890abcd", "AWS_REGION": "us-east-1" }
+}"#).unwrap();
+    std::fs::write(claude_dir.join("profiles/.current"), "work").unwrap();
+
+    let ccp = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    ccp(&["backup", "before-upgrade"]);
+    ccp(&["backup"]);
+    ccp(&["restore", "before-upgrade"]);
+
+    let meta = std::fs::read_to_string(claude_dir.join("backups/.meta/before-upgrade.json")).unwrap();
+    assert!(meta.contains(r#""origin": "manual""#));
+    assert!(meta.contains(r#""profile": "work""#));
+
+    // Unnamed backups are still taken by the user
+    let unnamed = std::fs::read_dir(claude_dir.join("backups/.meta")).unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.file_name().unwrap().to_string_lossy().starts_with("backup-"))
+        .unwrap();
+    assert!(std::fs::read_to_string(unnamed).unwrap().contains(r#""origin": "manual""#));
+
+    let stdout = ccp(&["backup", "list"]);
+    assert!(stdout.contains("before-upgrade"));
+    assert!(!stdout.contains("automatic"));
+    assert!(stdout.contains("pre-restore"));
+    assert!(stdout.contains("work"));
+
+    let stdout = ccp(&["backup", "show", "before-upgrade"]);
+    assert!(stdout.contains("Origin:  manual"));
+    assert!(stdout.contains("****abcd"));
+    assert!(!stdout.contains("sk-ant"));
+    assert!(stdout.contains("us-east-1"));
+
+    ccp(&["backup", "delete", "before-upgrade", "--force"]);
+    assert!(!claude_dir.join("backups/before-upgrade.json").exists());
+    assert!(!claude_dir.join("backups/.meta/before-upgrade.json").exists());
+    assert!(!ccp(&["backup", "list"]).contains("before-upgrade"));
+}
+
+#[test]
+fn test_cli_snapshots() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let profiles_dir = claude_dir.join("profiles");
+    std::fs::write(profiles_dir.join("work.json"), r#"{"model": "opus"}"#).unwrap();
+    std::fs::write(profiles_dir.join("old.json"), r#"{"model": "haiku"}"#).unwrap();
+
+    let ccp = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    ccp(&["use", "work"]);
+    ccp(&["describe", "work", "Main setup"]);
+    let stdout = ccp(&["snapshot", "create", "stable"]);
+    assert!(stdout.contains("3 profiles"));
+    assert!(claude_dir.join("backups/snapshots/stable.json").exists());
+
+    // Change everything the snapshot covers
+    ccp(&["set", "model", "sonnet", "--profile", "work"]);
+    ccp(&["delete", "old", "--force"]);
+    ccp(&["create", "new"]);
+    ccp(&["use", "new"]);
+
+    let stdout = ccp(&["snapshot", "restore", "stable", "--yes"]);
+    assert!(stdout.contains("+ old"));
+    assert!(stdout.contains("- new"));
+    assert!(stdout.contains("~ work"));
+    assert!(stdout.contains("Current profile: new → work"));
+
+    assert!(profiles_dir.join("old.json").exists());
+    assert!(!profiles_dir.join("new.json").exists());
+    assert_eq!(std::fs::read_to_string(profiles_dir.join("work.json")).unwrap(), r#"{"model": "opus"}"#);
+    assert_eq!(std::fs::read_to_string(profiles_dir.join(".current")).unwrap().trim(), "work");
+    assert!(std::fs::read_to_string(profiles_dir.join(".meta/work.json")).unwrap().contains("Main setup"));
+    let settings = std::fs::read_to_string(claude_dir.join("settings.json")).unwrap();
+    assert!(settings.contains("opus"));
+
+    // The replaced state was kept as its own snapshot
+    let stdout = ccp(&["snapshot", "list"]);
+    assert!(stdout.contains("stable"));
+    assert!(stdout.contains("pre-restore-"));
+
+    let stdout = ccp(&["snapshot", "restore", "stable", "--yes"]);
+    assert!(stdout.contains("Nothing to restore"));
+}
+
+#[test]
+fn test_cli_restore_sources_and_current() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let profiles_dir = claude_dir.join("profiles");
+    std::fs::write(profiles_dir.join("work.json"), r#"{"model": "opus"}"#).unwrap();
+    std::fs::write(profiles_dir.join("home.json"), r#"{"model": "haiku"}"#).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).args(args).output().unwrap()
+    };
+    let ccp = |args: &[&str]| {
+        let output = run(args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let current = || std::fs::read_to_string(profiles_dir.join(".current")).ok();
+
+    ccp(&["use", "work"]);
+    ccp(&["backup", "on-work"]);
+    ccp(&["use", "home"]);
+
+    // Restoring a backup that matches a profile points .current at it
+    let stdout = ccp(&["restore", "on-work"]);
+    assert!(stdout.contains("Current profile is now 'work'"));
+    assert_eq!(current().as_deref(), Some("work"));
+
+    // Hand-edited settings match no profile, so .current is cleared
+    std::fs::write(claude_dir.join("settings.json"), r#"{"model": "sonnet"}"#).unwrap();
+    ccp(&["backup", "custom"]);
+    ccp(&["use", "home"]);
+    let stdout = ccp(&["restore", "custom"]);
+    assert!(stdout.contains("no longer matches profile 'home'"));
+    assert!(current().is_none());
+
+    // No silent fallback from a missing backup to a profile
+    let output = run(&["restore", "home"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--profile"));
+    let output = run(&["restore", "home", "--backup"]);
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("--profile"));
+    let stdout = ccp(&["restore", "home", "--profile"]);
+    assert!(stdout.contains("Restored settings from profile 'home'"));
+    assert_eq!(current().as_deref(), Some("home"));
+
+    // Saving a backup into a profile leaves settings alone unless --switch
+    ccp(&["restore", "custom", "--to-profile", "custom"]);
+    assert!(std::fs::read_to_string(profiles_dir.join("custom.json")).unwrap().contains("sonnet"));
+    assert!(std::fs::read_to_string(claude_dir.join("settings.json")).unwrap().contains("haiku"));
+    assert!(!run(&["restore", "custom", "--to-profile", "custom"]).status.success());
+    ccp(&["restore", "custom", "--to-profile", "custom", "--force", "--switch"]);
+    assert!(std::fs::read_to_string(claude_dir.join("settings.json")).unwrap().contains("sonnet"));
+    assert_eq!(current().as_deref(), Some("custom"));
+
+    // Writing into the current profile re-applies it
+    let stdout = ccp(&["restore", "on-work", "--to-profile", "custom", "--force"]);
+    assert!(stdout.contains("settings.json was updated too"));
+    assert!(std::fs::read_to_string(claude_dir.join("settings.json")).unwrap().contains("opus"));
+    assert_eq!(current().as_deref(), Some("custom"));
+}
+
+#[test]
+fn test_cli_restore_without_name_needs_terminal() {
+    let (home_dir, _) = create_test_home();
+    let ccp_err = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).write_stdin("").output().unwrap();
+        assert!(!output.status.success());
+        String::from_utf8_lossy(&output.stderr).to_string()
+    };
+
+    assert!(ccp_err(&["restore"]).contains("Pass a backup name"));
+    assert!(ccp_err(&["restore", "--profile"]).contains("Give the profile to restore from"));
+}
+
+#[test]
+fn test_cli_history_log_show_revert() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let profiles_dir = claude_dir.join("profiles");
+    std::fs::write(profiles_dir.join("work.json"), "{\n  \"model\": \"sonnet\"\n}").unwrap();
+
+    let ccp = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        let output = cmd.env("HOME", home_dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    assert!(ccp(&["log", "work"]).contains("No history"));
+    ccp(&["set", "model", "opus", "--profile", "work"]);
+    ccp(&["set", "env.API_URL", "https://example.test", "--profile", "work"]);
+    // Saving identical content records nothing
+    ccp(&["set", "model", "opus", "--profile", "work"]);
+
+    let stdout = ccp(&["log", "work"]);
+    assert!(stdout.contains("@2"));
+    assert!(stdout.contains("Set env.API_URL in work"));
+    assert!(stdout.contains("(env.API_URL)"));
+    // Values may be secrets, so they are never recorded
+    assert!(!stdout.contains("example.test"));
+    let history = profiles_dir.join(".history");
+    for entry in std::fs::read_dir(&history).unwrap() {
+        let content = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        assert!(!content.contains("https://example.test"));
+    }
+    assert!(stdout.contains("(model)"));
+    assert!(!stdout.contains("@3"));
+
+    assert!(ccp(&["show", "work@1"]).contains("sonnet"));
+    assert!(ccp(&["show", "work"]).contains("API_URL"));
+
+    ccp(&["use", "work"]);
+    let stdout = ccp(&["revert", "work", "1"]);
+    assert!(stdout.contains("Reverted 'work' to revision 1 and applied it"));
+    assert!(std::fs::read_to_string(profiles_dir.join("work.json")).unwrap().contains("sonnet"));
+    assert!(std::fs::read_to_string(claude_dir.join("settings.json")).unwrap().contains("sonnet"));
+    // The revert is recorded too, so it can be undone
+    assert!(ccp(&["show", "work@3"]).contains("API_URL"));
+
+    // History follows renames and honours the limit
+    ccp(&["rename", "work", "job"]);
+    assert!(ccp(&["log", "job"]).contains("@3"));
+    std::fs::write(claude_dir.join("ccp.json"), r#"{"historyLimit": 2}"#).unwrap();
+    ccp(&["set", "model", "haiku", "--profile", "job"]);
+    let stdout = ccp(&["log", "job"]);
+    assert!(stdout.contains("@4"));
+    assert!(stdout.contains("@3"));
+    assert!(!stdout.contains("@2"));
+}
+
+#[test]
+fn test_cli_git_backed_profiles() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    let remote = home_dir.path().join("remote.git");
+
+    let ccp_cmd = || {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com");
+        cmd
+    };
+    let ccp = |args: &[&str]| {
+        let output = ccp_cmd().args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let output = std::process::Command::new("git")
+        .args(["init", "--quiet", "--bare"])
+        .arg(&remote)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    ccp(&["init", "--git"]);
+    assert!(profiles_dir.join(".git").exists());
+    let gitignore = std::fs::read_to_string(profiles_dir.join(".gitignore")).unwrap();
+    assert!(gitignore.contains(".vars.json"));
+    assert!(gitignore.contains(".current"));
+
+    std::fs::write(profiles_dir.join(".vars.json"), r#"{"API_KEY": "sk-secret"}"#).unwrap();
+    ccp(&["create", "work"]);
+    ccp(&["set", "env.API_KEY", "${API_KEY}", "--profile", "work"]);
+    ccp(&["rename", "work", "job"]);
+    ccp(&["get", "model", "--profile", "job"]);
+
+    let log = ccp(&["git", "log", "--format=%s"]);
+    let subjects: Vec<&str> = log.lines().collect();
+    assert_eq!(subjects, [
+        "Rename profile work to job",
+        "Set env.API_KEY in work",
+        "Create profile work",
+        "Initialize ccp profiles",
+    ]);
+
+    let tracked = ccp(&["git", "ls-files"]);
+    assert!(tracked.contains("job.json"));
+    assert!(!tracked.contains(".vars.json"));
+    assert!(!tracked.contains(".current"));
+
+    // Switching only touches machine-local files, so the tree stays clean
+    ccp(&["use", "job"]);
+    assert!(ccp(&["git", "status", "--porcelain"]).is_empty());
+    assert!(ccp(&["describe", "job"]).contains("Last used:"));
+
+    // Passthrough works with a local bare remote
+    ccp(&["git", "remote", "add", "origin", remote.to_str().unwrap()]);
+    ccp(&["git", "push", "--quiet", "origin", "HEAD:refs/heads/main"]);
+    let output = std::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(&remote)
+        .args(["log", "--format=%s", "-1", "main"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Rename profile work to job");
+
+    // A profiles directory inside another repository isn't treated as one
+    let (other_home, _) = create_test_home();
+    let other = || {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", other_home.path())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "user.useConfigOnly")
+            .env("GIT_CONFIG_VALUE_0", "true");
+        cmd
+    };
+    let output = std::process::Command::new("git")
+        .args(["init", "--quiet"])
+        .arg(other_home.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let output = other().args(["git", "status"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a git repository"));
+
+    // Without a configured identity, init still commits under a fallback one
+    other().args(["init", "--git"]).assert().success();
+    let output = other().args(["create", "x"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("not committed"));
+    let output = other().args(["git", "log", "--format=%an <%ae> %s"]).output().unwrap();
+    let log = String::from_utf8_lossy(&output.stdout);
+    assert!(log.contains("ccp <ccp@localhost> Initialize ccp profiles"), "{}", log);
+    assert!(log.contains("ccp <ccp@localhost> Create profile x"), "{}", log);
+
+    // Snapshots leave the repository out and keep it across a restore
+    ccp(&["snapshot", "create", "with-git"]);
+    let snapshot = std::fs::read_to_string(home_dir.path().join(".claude/backups/snapshots/with-git.json")).unwrap();
+    assert!(!snapshot.contains(".git/"));
+    ccp(&["delete", "job", "--force"]);
+    ccp(&["snapshot", "restore", "with-git", "--yes"]);
+    assert!(profiles_dir.join("job.json").exists());
+    let log = ccp(&["git", "log", "--format=%s", "-2"]);
+    assert_eq!(log.lines().collect::<Vec<_>>(), ["Restore snapshot with-git", "Delete profile job"]);
+
+    // git's exit code is passed through
+    assert!(!ccp_cmd().args(["git", "show", "no-such-ref"]).output().unwrap().status.success());
+}
+
+#[test]
+fn test_cli_undo_redo_journal() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let profiles_dir = claude_dir.join("profiles");
+    std::fs::write(profiles_dir.join("work.json"), r#"{"model": "opus"}"#).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).args(args).output().unwrap()
+    };
+    let ccp = |args: &[&str]| {
+        let output = run(args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let settings = || std::fs::read_to_string(claude_dir.join("settings.json")).unwrap_or_default();
+
+    assert!(ccp(&["journal"]).contains("Nothing to undo"));
+    ccp(&["use", "work"]);
+    ccp(&["list"]);
+    ccp(&["get", "model"]);
+    ccp(&["rename", "work", "job"]);
+    ccp(&["delete", "job", "--force"]);
+
+    // Read-only commands leave no entries
+    let stdout = ccp(&["journal"]);
+    assert!(stdout.contains("Delete profile job"));
+    assert!(stdout.contains("Rename profile work to job"));
+    assert!(stdout.contains("Switch to work"));
+    assert_eq!(std::fs::read_dir(claude_dir.join("backups/journal")).unwrap().count(), 3);
+    // Revision history and trash are not journaled
+    for entry in std::fs::read_dir(claude_dir.join("backups/journal")).unwrap() {
+        let journaled = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        assert!(!journaled.contains("profiles/.history"), "{}", journaled);
+        assert!(!journaled.contains("profiles/.trash"), "{}", journaled);
+    }
+
+    let stdout = ccp(&["undo"]);
+    assert!(stdout.contains("Undid: Delete profile job"));
+    assert!(stdout.contains("+ job"));
+    assert!(profiles_dir.join("job.json").exists());
+
+    let stdout = ccp(&["undo", "--steps", "2"]);
+    assert!(stdout.contains("Undid: Rename profile work to job"));
+    assert!(stdout.contains("Undid: Switch to work"));
+    assert!(profiles_dir.join("work.json").exists());
+    assert!(!profiles_dir.join("job.json").exists());
+    assert_eq!(std::fs::read_to_string(profiles_dir.join(".current")).unwrap(), "default");
+    assert!(!settings().contains("opus"));
+    assert!(!run(&["undo"]).status.success());
+
+    let stdout = ccp(&["redo"]);
+    assert!(stdout.contains("Redid: Switch to work"));
+    assert!(settings().contains("opus"));
+    assert!(ccp(&["journal"]).contains("(undone)"));
+
+    // A new change drops what was left to redo
+    ccp(&["create", "fresh"]);
+    let output = run(&["redo"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Nothing to redo"));
+
+    // The journal is bounded
+    std::fs::write(claude_dir.join("ccp.json"), r#"{"journalLimit": 2}"#).unwrap();
+    ccp(&["set", "model", "a", "--profile", "fresh"]);
+    ccp(&["set", "model", "b", "--profile", "fresh"]);
+    assert_eq!(std::fs::read_dir(claude_dir.join("backups/journal")).unwrap().count(), 2);
+}
+
+#[test]
+fn test_cli_trash_and_undelete() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    std::fs::create_dir_all(profiles_dir.join("clients")).unwrap();
+    std::fs::write(profiles_dir.join("clients/acme.yaml"), "model: opus\n").unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).args(args).output().unwrap()
+    };
+    let ccp = |args: &[&str]| {
+        let output = run(args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    ccp(&["describe", "clients/acme", "ACME account"]);
+    ccp(&["set", "model", "sonnet", "--profile", "clients/acme"]);
+    assert!(ccp(&["delete", "clients/acme", "--force"]).contains("ccp undelete clients/acme"));
+    assert!(!profiles_dir.join("clients").exists());
+    assert!(!ccp(&["list"]).contains("acme"));
+
+    let stdout = ccp(&["trash", "list"]);
+    assert!(stdout.contains("clients/acme"));
+    assert!(stdout.contains("just now"));
+
+    // Content, format, metadata and history all come back
+    ccp(&["undelete", "clients/acme"]);
+    assert!(std::fs::read_to_string(profiles_dir.join("clients/acme.yaml")).unwrap().contains("sonnet"));
+    assert!(ccp(&["describe", "clients/acme"]).contains("ACME account"));
+    assert!(ccp(&["log", "clients/acme"]).contains("@1"));
+    assert!(ccp(&["trash", "list"]).contains("empty"));
+
+    // A name that is taken again needs --as
+    ccp(&["delete", "clients/acme", "--force"]);
+    ccp(&["create", "clients/acme"]);
+    let output = run(&["undelete", "clients/acme"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--as"));
+    ccp(&["undelete", "clients/acme", "--as", "clients/acme-old"]);
+    assert!(profiles_dir.join("clients/acme-old.yaml").exists());
+
+    // Emptying by age only removes older entries
+    ccp(&["delete", "clients/acme", "--force"]);
+    assert!(ccp(&["trash", "empty", "--older-than", "1d", "--force"]).contains("Nothing to remove"));
+    assert!(ccp(&["trash", "list"]).contains("clients/acme"));
+    ccp(&["trash", "empty", "--force"]);
+    assert!(ccp(&["trash", "list"]).contains("empty"));
+}
+
+#[test]
+fn test_cli_archive_profiles() {
+    let (home_dir, _) = create_test_home();
+    let profiles_dir = home_dir.path().join(".claude/profiles");
+    std::fs::write(profiles_dir.join("work.json"), r#"{"model": "opus"}"#).unwrap();
+    std::fs::write(profiles_dir.join("old-client.json"), r#"{"model": "haiku"}"#).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).args(args).output().unwrap()
+    };
+    let ccp = |args: &[&str]| {
+        let output = run(args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    ccp(&["archive", "old-client"]);
+    let stdout = ccp(&["list"]);
+    assert!(stdout.contains("work"));
+    assert!(!stdout.contains("old-client"));
+    assert!(stdout.contains("1 archived profile(s) hidden"));
+
+    let stdout = ccp(&["list", "--all"]);
+    assert!(stdout.contains("old-client"));
+    assert!(stdout.contains("(archived)"));
+
+    // Archived profiles still work where they are named explicitly
+    let output = run(&["use", "old-client"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--archived"));
+    // Looser matches skip archived profiles unless --archived is given
+    let output = run(&["use", "old-"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not exist"));
+    let output = run(&["use", "old-clent"]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("old-client"));
+    ccp(&["use", "old-", "--archived"]);
+    ccp(&["use", "old-client", "--archived"]);
+    assert!(ccp(&["export", "old-client"]).contains("haiku"));
+    assert!(ccp(&["diff", "old-client", "work"]).contains("opus"));
+
+    ccp(&["unarchive", "old-client"]);
+    assert!(ccp(&["list"]).contains("old-client"));
+    ccp(&["use", "work"]);
+    ccp(&["use", "old-client"]);
+}
+
+#[test]
+fn test_cli_undo_only_reverts_what_the_command_changed() {
+    let (home_dir, _) = create_test_home();
+    let claude_dir = home_dir.path().join(".claude");
+    let profiles_dir = claude_dir.join("profiles");
+    std::fs::write(profiles_dir.join("work.json"), r#"{"model": "opus"}"#).unwrap();
+    std::fs::write(profiles_dir.join("other.json"), r#"{"model": "haiku"}"#).unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("ccp").unwrap();
+        cmd.env("HOME", home_dir.path()).args(args).output().unwrap()
+    };
+    let ccp = |args: &[&str]| {
+        let output = run(args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let read = |path: &std::path::Path| std::fs::read_to_string(path).unwrap();
+    let hand_edited = r#"{"model": "x", "permissions": {"allow": ["Bash(ls)"]}}"#;
+
+    // Files the command did not touch keep their later edits
+    ccp(&["set", "model", "y", "--profile", "work"]);
+    std::fs::write(profiles_dir.join("other.json"), r#"{"model": "edited"}"#).unwrap();
+    std::fs::write(claude_dir.join("settings.json"), hand_edited).unwrap();
+    ccp(&["undo"]);
+    assert!(read(&profiles_dir.join("work.json")).contains("opus"));
+    assert!(read(&profiles_dir.join("other.json")).contains("edited"));
+    assert_eq!(read(&claude_dir.join("settings.json")), hand_edited);
+
+    // Files it did touch are only overwritten with --force
+    ccp(&["set", "model", "x", "--profile", "default"]);
+    std::fs::write(claude_dir.join("settings.json"), r#"{"model": "x", "theme": "dark"}"#).unwrap();
+    let output = run(&["undo"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("settings.json"), "{}", stderr);
+    assert!(stderr.contains("--force"));
+    assert!(read(&profiles_dir.join("default.json")).contains("\"x\""));
+    ccp(&["undo", "--force"]);
+    assert!(read(&profiles_dir.join("default.json")).contains("sonnet-4"));
+    assert_eq!(read(&claude_dir.join("settings.json")), hand_edited);
+
+    // A command that fails part way is journaled with what it wrote
+    ccp(&["set", "model", "sonnet", "--profile", "work"]);
+    std::fs::create_dir_all(profiles_dir.join(".history/job@1.json")).unwrap();
+    assert!(!run(&["rename", "work", "job"]).status.success());
+    assert!(profiles_dir.join("job.json").exists());
+    assert!(ccp(&["journal"]).contains("Rename profile work to job (failed)"));
+    ccp(&["undo"]);
+    assert!(!profiles_dir.join("job.json").exists());
+    assert!(profiles_dir.join("work.json").exists());
+}